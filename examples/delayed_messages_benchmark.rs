@@ -0,0 +1,141 @@
+//! This example measures the throughput and memory use of delayed messages.
+//! A large burst of delayed messages is sent to a standalone mailbox, and the time taken to schedule them all, the time taken to deliver them all once their delay has elapsed, and the heap memory they occupy while they wait are reported.
+//! The same burst is then sent using one task per message (the design which the Postmaster's scheduler replaced), so that the two can be compared side by side.
+//! For meaningful results, run it in release mode: `cargo run --release --example delayed_messages_benchmark`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::sync::mpsc::{Receiver, channel};
+use tokio::sync::watch;
+use tokio::time::{self, Duration, Instant};
+
+enum Payloads {
+    Tick,
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Address {
+    Benchmark,
+    Sink,
+}
+
+init_postmaster!(Address, Payloads);
+
+const MESSAGE_COUNT: usize = 20_000;
+const DELAY: Duration = Duration::from_millis(100);
+
+/// Wraps the system allocator to keep count of the bytes currently allocated on the heap.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The results of sending a burst of delayed messages.
+struct Measurement {
+    /// The time taken to send every message
+    scheduled: Duration,
+    /// How long after the delay elapsed the last message was delivered
+    lag: Duration,
+    /// The heap memory occupied by the messages while they were waiting to be delivered
+    heap_bytes: usize,
+}
+
+/// Sends a burst of delayed messages using `send_delayed()`, keeping hold of whatever it returns so that the messages could still be cancelled, and waits for them all to be delivered.
+async fn measure<H>(
+    receiver: &mut Receiver<postmaster::Message>,
+    mut send_delayed: impl AsyncFnMut() -> H,
+) -> Measurement {
+    let mut handles = Vec::with_capacity(MESSAGE_COUNT);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..MESSAGE_COUNT {
+        handles.push(send_delayed().await);
+    }
+    let scheduled = start.elapsed();
+    let heap_bytes = ALLOCATED.load(Ordering::Relaxed).saturating_sub(allocated);
+
+    for _ in 0..MESSAGE_COUNT {
+        let Payloads::Tick = receiver.recv().await.unwrap().payload;
+    }
+    Measurement {
+        scheduled,
+        lag: start.elapsed().saturating_sub(DELAY),
+        heap_bytes,
+    }
+}
+
+/// Sends a delayed message in the way the Postmaster did before it had a scheduler: with a task of its own, which can be rescheduled through a watch channel until it is delivered.
+fn spawn_task_per_message() -> watch::Sender<Option<Duration>> {
+    let (control, mut commands) = watch::channel(None);
+    let mut deadline = Instant::now() + DELAY;
+    tokio::spawn(async move {
+        loop {
+            match time::timeout_at(deadline, commands.changed()).await {
+                Err(_) => break,
+                Ok(Err(_)) => {
+                    time::sleep_until(deadline).await;
+                    break;
+                }
+                Ok(Ok(())) => {
+                    if let Some(delay) = *commands.borrow_and_update() {
+                        deadline = Instant::now() + delay;
+                    }
+                }
+            }
+        }
+        drop(commands);
+        let _ = postmaster::send(Address::Sink, Address::Benchmark, Payloads::Tick).await;
+    });
+    control
+}
+
+#[tokio::main]
+async fn main() {
+    let (sender, mut receiver) = channel(MESSAGE_COUNT);
+    postmaster::register(Address::Sink, sender, postmaster::OverflowPolicy::Block)
+        .await
+        .unwrap();
+
+    let scheduler = measure(&mut receiver, async || {
+        postmaster::message(Address::Sink, Address::Benchmark, Payloads::Tick)
+            .with_delay(DELAY)
+            .send()
+            .await
+            .unwrap()
+    })
+    .await;
+    let task_per_message = measure(&mut receiver, async || spawn_task_per_message()).await;
+
+    println!("{MESSAGE_COUNT} delayed messages, {DELAY:?} delay");
+    println!("{:<32}{:>16}{:>20}", "", "scheduler", "task per message");
+    println!(
+        "{:<32}{:>16?}{:>20?}",
+        "Time to schedule", scheduler.scheduled, task_per_message.scheduled
+    );
+    println!(
+        "{:<32}{:>16?}{:>20?}",
+        "Delivery lag after the delay", scheduler.lag, task_per_message.lag
+    );
+    println!(
+        "{:<32}{:>16}{:>20}",
+        "Heap in use per message (bytes)",
+        scheduler.heap_bytes / MESSAGE_COUNT,
+        task_per_message.heap_bytes / MESSAGE_COUNT
+    );
+}
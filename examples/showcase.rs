@@ -4,11 +4,10 @@
 //! "Hello" messages are then sent from the main task to the Agents, with the source address given as one of the other Agents.
 //! This will prompt the Agent to respond with its own "hello" back to the source, initiating an infinite loop.
 
-#![feature(variant_count)]
 use core::time::Duration;
 
 use polite_agent::{PoliteAgent, PoliteAgentConfig, PoliteAgentMessage};
-use post_haste::init_postmaster;
+use post_haste::{VariantIndex, init_postmaster};
 use tokio::time::sleep;
 
 /// This enum describes the messages used by the system.
@@ -26,7 +25,7 @@ enum Payloads {
 /// This enum provides all Agent addresses.
 /// Each Agent must be assigned a unique address upon registration with the Postmaster.
 /// As indicated by the signature of the Agent trait's `run()` method, Agents are expected to live for the lifetime of the application. This ensures that addresses are always valid and messages aren't accidentally sent to an unoccupied address.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, VariantIndex)]
 enum Address {
     AgentA,
     AgentB,
@@ -5,7 +5,6 @@
     reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
     holding buffers for the duration of a data transfer."
 )]
-#![feature(variant_count)]
 
 use bt_hci::controller::ExternalController;
 use embassy_executor::Spawner;
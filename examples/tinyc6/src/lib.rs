@@ -1,8 +1,7 @@
 #![no_std]
-#![feature(variant_count)]
 
 use embassy_executor::Spawner;
-use post_haste::init_postmaster;
+use post_haste::{VariantIndex, init_postmaster};
 
 use crate::polite_agent::PoliteAgent;
 
@@ -12,7 +11,7 @@ enum Payloads {
     Hello,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, VariantIndex)]
 enum Address {
     PoliteAgentA,
     PoliteAgentB,
@@ -1,9 +1,7 @@
 //! This example provides a very simple scenario of two Agents exchanging messages with each other.
-#![feature(variant_count)]
-
 use core::time::Duration;
 
-use post_haste::init_postmaster;
+use post_haste::{VariantIndex, init_postmaster};
 use tokio::time::sleep;
 
 use crate::polite_agent::PoliteAgent;
@@ -12,7 +10,7 @@ enum Payloads {
     Hello,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, VariantIndex)]
 enum Addresses {
     A,
     B,
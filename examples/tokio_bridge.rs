@@ -0,0 +1,164 @@
+//! This example simulates two devices linked by a serial stream, each running its own Postmaster `Instance`.
+//! The Controller on the main board asks the Motor on the motor board to change speed, without knowing that the Motor is on another device.
+//! A Bridge Agent on each device passes the messages across the link, which here is an in-memory stream (`tokio::io::duplex()`), in place of a UART.
+//! On Linux, a pseudo-terminal pair can be used instead, by wrapping each end in `FromTokio`.
+use post_haste::bridge::{Bridge, BridgeConfig, FromTokio};
+use post_haste::{VariantIndex, init_postmaster};
+use serde::{Deserialize, Serialize};
+use tokio::io::{DuplexStream, ReadHalf, WriteHalf, duplex, split};
+use tokio::sync::oneshot;
+
+use crate::controller_agent::ControllerAgent;
+use crate::motor_agent::MotorAgent;
+
+#[derive(Serialize, Deserialize)]
+enum Payloads {
+    SetSpeed(u16),
+    SpeedChanged(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, VariantIndex, Serialize, Deserialize)]
+enum Addresses {
+    Controller,
+    Motor,
+    Bridge,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+type SerialBridge = Bridge<
+    Addresses,
+    postmaster::Message,
+    FromTokio<ReadHalf<DuplexStream>>,
+    FromTokio<WriteHalf<DuplexStream>>,
+>;
+
+/// The addresses whose Agents live on the other device, as seen from each device
+static MAIN_BOARD_REMOTE: [Addresses; 1] = [Addresses::Motor];
+static MOTOR_BOARD_REMOTE: [Addresses; 1] = [Addresses::Controller];
+
+#[tokio::main]
+async fn main() {
+    let (main_board_end, motor_board_end) = duplex(256);
+
+    let motor_board = postmaster::Instance::new();
+    motor_board
+        .scope(async {
+            postmaster::register_agent!(Motor, MotorAgent, ()).unwrap();
+            start_bridge(motor_board_end, &MOTOR_BOARD_REMOTE).await;
+        })
+        .await;
+
+    let main_board = postmaster::Instance::new();
+    let (done, finished) = oneshot::channel();
+    main_board
+        .scope(async {
+            start_bridge(main_board_end, &MAIN_BOARD_REMOTE).await;
+            postmaster::register_agent!(Controller, ControllerAgent, done).unwrap();
+        })
+        .await;
+    finished.await.unwrap();
+
+    let received = motor_board.sync_scope(|| postmaster::get_diagnostics().messages_sent);
+    println!("The motor board's Postmaster delivered {received} messages");
+}
+
+/// Registers a Bridge which passes messages for the given remote addresses over one end of the link.
+async fn start_bridge(stream: DuplexStream, remote: &'static [Addresses]) {
+    let (reader, writer) = split(stream);
+    let config = BridgeConfig {
+        reader: FromTokio(reader),
+        writer: FromTokio(writer),
+        remote,
+    };
+    postmaster::register_agent!(Bridge, SerialBridge, config, 8).unwrap();
+    // The Bridge forwards the remote addresses to itself once it starts running, but doing so here means messages can be sent to them straight away
+    for &address in remote {
+        postmaster::forward(address, Addresses::Bridge)
+            .await
+            .unwrap();
+    }
+}
+
+mod controller_agent {
+    use post_haste::agent::{Agent, Inbox};
+    use tokio::sync::oneshot;
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct ControllerAgent {
+        address: Addresses,
+        done: Option<oneshot::Sender<()>>,
+    }
+
+    impl Agent for ControllerAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = oneshot::Sender<()>;
+
+        async fn create(address: Self::Address, config: Self::Config) -> Self {
+            Self {
+                address,
+                done: Some(config),
+            }
+        }
+
+        async fn run(mut self, mut inbox: Inbox<Self::Message>) -> ! {
+            for speed in [1200, 2400] {
+                postmaster::send(Addresses::Motor, self.address, Payloads::SetSpeed(speed))
+                    .await
+                    .unwrap();
+                let received_message = inbox.recv().await.unwrap();
+                if let Payloads::SpeedChanged(speed) = received_message.payload {
+                    println!(
+                        "Controller: the {:?} is now running at {speed} rpm",
+                        received_message.source
+                    );
+                }
+            }
+            let _ = self.done.take().unwrap().send(());
+            loop {
+                inbox.recv().await.unwrap();
+            }
+        }
+    }
+}
+
+mod motor_agent {
+    use post_haste::agent::{Agent, Inbox};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct MotorAgent {
+        address: Addresses,
+    }
+
+    impl Agent for MotorAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = ();
+
+        async fn create(address: Self::Address, _config: Self::Config) -> Self {
+            Self { address }
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                if let Payloads::SetSpeed(speed) = received_message.payload {
+                    println!(
+                        "Motor: setting speed to {speed} rpm, as requested by the {:?}",
+                        received_message.source
+                    );
+                    postmaster::send(
+                        received_message.source,
+                        self.address,
+                        Payloads::SpeedChanged(speed),
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! This example simulates two devices running the same firmware side by side in one process.
+//! Each device has its own Postmaster `Instance`, so both can register a Thermostat Agent at the same address without interfering with each other.
+//! The Thermostat reports its reading once per second using a periodic message, which is delivered by its own instance's scheduler.
+use core::time::Duration;
+
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::sync::mpsc::channel;
+
+use crate::thermostat_agent::ThermostatAgent;
+
+enum Payloads {
+    Measure,
+    Reading(i32),
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Addresses {
+    Display,
+    Thermostat,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+#[tokio::main]
+async fn main() {
+    let kitchen = postmaster::Instance::new();
+    let cellar = postmaster::Instance::new();
+    let kitchen_device = tokio::spawn(kitchen.scope(run_device("kitchen", 21)));
+    let cellar_device = tokio::spawn(cellar.scope(run_device("cellar", 12)));
+    kitchen_device.await.unwrap();
+    cellar_device.await.unwrap();
+
+    // Each instance keeps its own diagnostics
+    let sent = kitchen.sync_scope(|| postmaster::get_diagnostics().messages_sent);
+    println!("The kitchen device sent {sent} messages");
+}
+
+/// The "firmware" of a device, which uses the free Postmaster functions as normal.
+async fn run_device(name: &'static str, temperature: i32) {
+    let (sender, mut display) = channel(4);
+    postmaster::register(
+        Addresses::Display,
+        sender,
+        postmaster::OverflowPolicy::Block,
+    )
+    .await
+    .unwrap();
+    postmaster::register_agent!(Thermostat, ThermostatAgent, temperature).unwrap();
+    postmaster::message(Addresses::Thermostat, Addresses::Display, Payloads::Measure)
+        .with_period(Duration::from_secs(1), |_| Payloads::Measure)
+        .with_repeat_count(3)
+        .send()
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let message: postmaster::Message = display.recv().await.unwrap();
+        if let Payloads::Reading(reading) = message.payload {
+            println!("The {name} is {reading}°C");
+        }
+    }
+}
+
+mod thermostat_agent {
+    use post_haste::agent::{Agent, Inbox};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct ThermostatAgent {
+        address: Addresses,
+        temperature: i32,
+    }
+
+    impl Agent for ThermostatAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = i32;
+
+        async fn create(address: Self::Address, config: Self::Config) -> Self {
+            Self {
+                address,
+                temperature: config,
+            }
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                if let Payloads::Measure = received_message.payload {
+                    postmaster::send(
+                        received_message.source,
+                        self.address,
+                        Payloads::Reading(self.temperature),
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! This example demonstrates an Agent with a priority queue.
+//! A backlog of routine telemetry builds up in the Monitor's queue, followed by a fault.
+//! As the fault is sent with a higher priority, the Monitor receives it before any of the telemetry.
+use core::time::Duration;
+
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::time::sleep;
+
+use crate::monitor_agent::MonitorAgent;
+use crate::postmaster::Priority;
+
+enum Payloads {
+    Telemetry(u32),
+    Fault,
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Addresses {
+    Sensor,
+    Monitor,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+#[tokio::main]
+async fn main() {
+    postmaster::register_agent!(Monitor, MonitorAgent, (), priority_queue = 8).unwrap();
+
+    for reading in 0..5 {
+        postmaster::send(
+            Addresses::Monitor,
+            Addresses::Sensor,
+            Payloads::Telemetry(reading),
+        )
+        .await
+        .unwrap();
+    }
+    postmaster::message(Addresses::Monitor, Addresses::Sensor, Payloads::Fault)
+        .with_priority(Priority::Critical)
+        .send()
+        .await
+        .unwrap();
+
+    sleep(Duration::from_secs(1)).await;
+}
+
+mod monitor_agent {
+    use post_haste::agent::{Agent, Inbox};
+    use tokio::time::{Duration, sleep};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct MonitorAgent;
+
+    impl Agent for MonitorAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = ();
+
+        async fn create(_address: Self::Address, _config: Self::Config) -> Self {
+            Self
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            // Give the backlog a chance to build up before handling any messages
+            sleep(Duration::from_millis(100)).await;
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                match received_message.payload {
+                    Payloads::Telemetry(reading) => println!("Telemetry reading {reading}"),
+                    Payloads::Fault => println!("Fault reported by {:?}!", received_message.source),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! This example demonstrates recording the messages delivered by the Postmaster, and replaying them into a fresh set of Agents.
+//! A Monitor Agent raises an alarm whenever a reading from the Sensor is too high.
+//! The readings are recorded, saved to a file, and then replayed into a new Monitor within a separate `Instance`, which raises the same alarm at the same time.
+use core::time::Duration;
+
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::sync::mpsc::{Receiver, channel};
+use tokio::time::{Instant, sleep};
+
+use crate::monitor_agent::MonitorAgent;
+
+enum Payloads {
+    Reading(u8),
+    Alarm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, VariantIndex)]
+enum Addresses {
+    Sensor,
+    Monitor,
+    Siren,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+/// Converts a payload to bytes for the recording.
+fn encode(payload: &Payloads) -> Vec<u8> {
+    match payload {
+        Payloads::Reading(value) => vec![0, *value],
+        Payloads::Alarm => vec![1],
+    }
+}
+
+/// Converts the bytes in a recording back to a payload.
+fn decode(bytes: &[u8]) -> Option<Payloads> {
+    match bytes {
+        [0, value] => Some(Payloads::Reading(*value)),
+        [1] => Some(Payloads::Alarm),
+        _ => None,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let _siren = start_system().await;
+    postmaster::start_recording(postmaster::RecordingTarget::RingBuffer(64), encode).unwrap();
+    for value in [40, 60, 120, 50] {
+        postmaster::send(
+            Addresses::Monitor,
+            Addresses::Sensor,
+            Payloads::Reading(value),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    let trace = postmaster::stop_recording().unwrap().unwrap();
+    if let Some(alarm) = trace
+        .records
+        .iter()
+        .find(|record| record.destination == Addresses::Siren)
+    {
+        println!("Alarm raised after {:?}", alarm.elapsed);
+    }
+    let path = std::env::temp_dir().join("post_haste_example.trace");
+    trace.save(&path).unwrap();
+    println!(
+        "Saved {} messages to {}",
+        trace.records.len(),
+        path.display()
+    );
+
+    // The alarm will be raised again by the new Monitor, so only the Sensor's readings are replayed
+    let mut trace = postmaster::Trace::load(&path).unwrap();
+    trace
+        .records
+        .retain(|record| record.source == Addresses::Sensor);
+    postmaster::Instance::new()
+        .scope(async {
+            let mut siren = start_system().await;
+            let started = Instant::now();
+            let (result, alarm) = tokio::join!(postmaster::replay(&trace, decode), async {
+                siren.recv().await.unwrap();
+                started.elapsed()
+            });
+            result.unwrap();
+            println!("Replayed alarm raised after {alarm:?}");
+        })
+        .await;
+}
+
+/// Registers the Monitor, along with a mailbox which receives the alarms it raises.
+async fn start_system() -> Receiver<postmaster::Message> {
+    let (sender, siren) = channel(4);
+    postmaster::register(Addresses::Siren, sender, postmaster::OverflowPolicy::Block)
+        .await
+        .unwrap();
+    postmaster::register_agent!(Monitor, MonitorAgent, ()).unwrap();
+    siren
+}
+
+mod monitor_agent {
+    use post_haste::agent::{Agent, Inbox};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    const ALARM_THRESHOLD: u8 = 100;
+
+    pub(crate) struct MonitorAgent {
+        address: Addresses,
+    }
+
+    impl Agent for MonitorAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = ();
+
+        async fn create(address: Self::Address, _config: Self::Config) -> Self {
+            Self { address }
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                if let Payloads::Reading(value) = received_message.payload
+                    && value > ALARM_THRESHOLD
+                {
+                    postmaster::send(Addresses::Siren, self.address, Payloads::Alarm)
+                        .await
+                        .unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! This example demonstrates supervised Agents, which are restarted automatically if they panic.
+//! The Worker Agent panics whenever it is asked to crash.
+//! It is allowed to restart twice within ten seconds, after which it is left stopped and its address is unregistered.
+use core::time::Duration;
+
+use post_haste::supervisor::RestartStrategy;
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::time::sleep;
+
+use crate::worker_agent::{WorkerAgent, WorkerConfig};
+
+enum Payloads {
+    Work(u32),
+    Crash,
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Addresses {
+    Main,
+    Worker,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+#[tokio::main]
+async fn main() {
+    let config = WorkerConfig { name: "worker" };
+    postmaster::register_agent!(
+        Worker,
+        WorkerAgent,
+        config,
+        4,
+        restart = RestartStrategy::MaxRestartsInWindow {
+            max_restarts: 2,
+            window: Duration::from_secs(10),
+        }
+    )
+    .unwrap();
+
+    for round in 0..3 {
+        postmaster::send(Addresses::Worker, Addresses::Main, Payloads::Work(round))
+            .await
+            .unwrap();
+        postmaster::send(Addresses::Worker, Addresses::Main, Payloads::Crash)
+            .await
+            .unwrap();
+        // Give the supervisor a chance to restart the Agent
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    // The Worker has now exceeded its restart limit, so is no longer registered
+    let result = postmaster::send(Addresses::Worker, Addresses::Main, Payloads::Work(3)).await;
+    println!("Sending to the stopped Worker: {result:?}");
+
+    let diagnostics = postmaster::get_diagnostics();
+    println!(
+        "Agent panics: {}, Agent restarts: {}",
+        diagnostics.agent_panics, diagnostics.agent_restarts
+    );
+}
+
+mod worker_agent {
+    use post_haste::agent::{Agent, Inbox};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    #[derive(Clone)]
+    pub(crate) struct WorkerConfig {
+        pub(crate) name: &'static str,
+    }
+
+    pub(crate) struct WorkerAgent {
+        config: WorkerConfig,
+    }
+
+    impl Agent for WorkerAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = WorkerConfig;
+
+        async fn create(_address: Self::Address, config: Self::Config) -> Self {
+            println!("Starting {}", config.name);
+            Self { config }
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                match received_message.payload {
+                    Payloads::Work(job) => println!("{} handling job {job}", self.config.name),
+                    Payloads::Crash => panic!("{} was asked to crash", self.config.name),
+                }
+            }
+        }
+    }
+}
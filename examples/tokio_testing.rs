@@ -0,0 +1,98 @@
+//! This example demonstrates the test harness, which is enabled with the `testing` feature.
+//! Run it with `cargo run --example tokio_testing --features testing`.
+//! The harness is normally used within `#[tokio::test(start_paused = true)]` functions, but here it is driven from `main()` so that the example can be run directly.
+//! The Ponger Agent answers each ping with a pong after 100 ms, which the harness checks using a probe in place of the Tester, without waiting in real time.
+use core::time::Duration;
+
+use post_haste::testing::Harness;
+use post_haste::{VariantIndex, init_postmaster};
+
+use crate::ponger_agent::PongerAgent;
+
+enum Payloads {
+    Ping(u32),
+    Pong(u32),
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Addresses {
+    Tester,
+    Ponger,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+#[tokio::main(flavor = "current_thread", start_paused = true)]
+async fn main() {
+    let mut harness = Harness::<Addresses, postmaster::Message>::new().await;
+    harness.probe(Addresses::Tester).await;
+    postmaster::register_agent!(Ponger, PongerAgent, (), 4).unwrap();
+
+    postmaster::send(Addresses::Ponger, Addresses::Tester, Payloads::Ping(1))
+        .await
+        .unwrap();
+    postmaster::send(Addresses::Ponger, Addresses::Tester, Payloads::Ping(2))
+        .await
+        .unwrap();
+
+    // The pongs are delayed, so nothing has arrived yet
+    harness.advance(Duration::from_millis(99)).await;
+    harness.expect_no_messages().await;
+
+    harness.advance(Duration::from_millis(1)).await;
+    harness
+        .expect_message(Addresses::Tester, Addresses::Ponger, |message| {
+            matches!(message.payload, Payloads::Pong(1))
+        })
+        .await;
+    harness
+        .expect_message(Addresses::Tester, Addresses::Ponger, |message| {
+            matches!(message.payload, Payloads::Pong(2))
+        })
+        .await;
+    harness.expect_no_messages().await;
+
+    println!(
+        "Both pongs arrived after {:?} of virtual time",
+        harness.elapsed()
+    );
+}
+
+mod ponger_agent {
+    use core::time::Duration;
+
+    use post_haste::agent::{Agent, Inbox};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct PongerAgent {
+        address: Addresses,
+    }
+
+    impl Agent for PongerAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = ();
+
+        async fn create(address: Self::Address, _config: Self::Config) -> Self {
+            Self { address }
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                if let Payloads::Ping(count) = received_message.payload {
+                    postmaster::message(
+                        received_message.source,
+                        self.address,
+                        Payloads::Pong(count),
+                    )
+                    .with_delay(Duration::from_millis(100))
+                    .send()
+                    .await
+                    .unwrap();
+                }
+            }
+        }
+    }
+}
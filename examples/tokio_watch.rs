@@ -0,0 +1,73 @@
+//! This example demonstrates an Agent with a watch mailbox.
+//! An IMU publishes readings far faster than the Display can show them.
+//! As the Display's mailbox only holds the latest reading, the IMU never waits and the Display always shows the newest reading.
+use core::time::Duration;
+
+use post_haste::{VariantIndex, init_postmaster};
+use tokio::time::sleep;
+
+use crate::display_agent::DisplayAgent;
+
+enum Payloads {
+    Heading(u32),
+}
+
+#[derive(Debug, Clone, Copy, VariantIndex)]
+enum Addresses {
+    Imu,
+    Display,
+}
+
+init_postmaster!(Addresses, Payloads);
+
+#[tokio::main]
+async fn main() {
+    postmaster::register_agent!(Display, DisplayAgent, (), watch).unwrap();
+
+    for heading in 0..100 {
+        postmaster::send(
+            Addresses::Display,
+            Addresses::Imu,
+            Payloads::Heading(heading),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(2)).await;
+    }
+
+    sleep(Duration::from_millis(100)).await;
+    println!(
+        "{} stale readings were never displayed",
+        postmaster::get_diagnostics().dropped_messages(Addresses::Display)
+    );
+}
+
+mod display_agent {
+    use post_haste::agent::{Agent, Inbox};
+    use tokio::time::{Duration, sleep};
+
+    use crate::{Addresses, Payloads, postmaster};
+
+    pub(crate) struct DisplayAgent;
+
+    impl Agent for DisplayAgent {
+        type Address = Addresses;
+        type Message = postmaster::Message;
+        type Config = ();
+
+        async fn create(_address: Self::Address, _config: Self::Config) -> Self {
+            Self
+        }
+
+        async fn run(self, mut inbox: Inbox<Self::Message>) -> ! {
+            loop {
+                let received_message = inbox.recv().await.unwrap();
+                match received_message.payload {
+                    Payloads::Heading(heading) => println!("Heading {heading}"),
+                }
+                // Refreshing the display is slow
+                sleep(Duration::from_millis(25)).await;
+            }
+        }
+    }
+}
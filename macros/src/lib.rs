@@ -0,0 +1,59 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, parse_macro_input};
+
+/// Derive `post_haste::VariantIndex` for an enum of unit variants, such as the Address or Topic enum passed to `init_postmaster!()`.
+/// Variants are indexed in the order they are declared, starting from 0.
+#[proc_macro_derive(VariantIndex)]
+pub fn derive_variant_index(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_variant_index(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_variant_index(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "VariantIndex can only be derived for enums",
+        ));
+    };
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|variant| !matches!(variant.fields, Fields::Unit))
+    {
+        return Err(Error::new_spanned(
+            variant,
+            "VariantIndex can only be derived for enums whose variants have no fields",
+        ));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let variants: Vec<_> = data.variants.iter().map(|variant| &variant.ident).collect();
+    let indices: Vec<_> = (0..variants.len())
+        .map(proc_macro2::Literal::usize_unsuffixed)
+        .collect();
+    let count = variants.len();
+
+    Ok(quote! {
+        impl #impl_generics ::post_haste::VariantIndex for #name #type_generics #where_clause {
+            const VARIANT_COUNT: usize = #count;
+
+            fn variant_index(&self) -> usize {
+                match *self {
+                    #(Self::#variants => #indices,)*
+                }
+            }
+
+            fn from_variant_index(index: usize) -> ::core::option::Option<Self> {
+                match index {
+                    #(#indices => ::core::option::Option::Some(Self::#variants),)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+    })
+}
@@ -1,12 +1,494 @@
 #[cfg(target_os = "none")]
-use embassy_sync::channel::DynamicReceiver as Receiver;
+use core::future::poll_fn;
+#[cfg(target_os = "none")]
+use core::task::{Context, Poll};
+#[cfg(target_os = "none")]
+use embassy_sync::blocking_mutex::raw::RawMutex;
+#[cfg(target_os = "none")]
+use embassy_sync::channel::{
+    Channel, DynamicReceiver as Receiver, DynamicSender as Sender, TryReceiveError, TrySendError,
+};
+#[cfg(target_os = "none")]
+use embassy_sync::priority_channel::{Kind, PriorityChannel};
+#[cfg(target_os = "none")]
+use embassy_time::{Duration, Instant, WithTimeout};
+#[cfg(not(target_os = "none"))]
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError, TryRecvError, TrySendError};
 #[cfg(not(target_os = "none"))]
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::{Receiver, Sender};
+#[cfg(not(target_os = "none"))]
+use tokio::time::Duration;
+
+use crate::overflow::Evict;
+#[cfg(target_os = "none")]
+use crate::priority::{Prioritised, Queued};
+#[cfg(not(target_os = "none"))]
+use crate::queue::{QueueReceiver, QueueSender};
+
+/// The receiving end of an Agent's message queue.
+/// This is either an ordinary queue (`Channel`), or a priority queue (`PriorityChannel`) holding its messages as `Queued` items.
+#[cfg(target_os = "none")]
+pub struct Inbox<T: 'static> {
+    receiver: ReceiveQueue<T>,
+    on_receive: Option<fn(&T)>,
+}
+
+#[cfg(target_os = "none")]
+enum ReceiveQueue<T: 'static> {
+    Fifo(Receiver<'static, T>),
+    Priority(&'static dyn PriorityQueue<T>),
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> Inbox<T> {
+    /// Sets a function to be called with each message as it is received.
+    /// `register_agent!()` uses this to measure the latency of messages.
+    pub fn with_receive_hook(self, hook: fn(&T)) -> Self {
+        Self {
+            on_receive: Some(hook),
+            ..self
+        }
+    }
+
+    /// Receives the next message, waiting until one is available.
+    pub async fn receive(&self) -> T {
+        poll_fn(|cx| self.poll_receive(cx)).await
+    }
+
+    /// Receives the next message if there is one, without waiting.
+    pub fn try_receive(&self) -> Result<T, TryReceiveError> {
+        let message = match &self.receiver {
+            ReceiveQueue::Fifo(receiver) => receiver.try_receive(),
+            ReceiveQueue::Priority(queue) => queue.try_receive(),
+        };
+        message.map(|message| self.received(message))
+    }
+
+    /// Polls the queue for the next message, registering the task to be woken when one is available.
+    pub fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T> {
+        let message = match &self.receiver {
+            ReceiveQueue::Fifo(receiver) => receiver.poll_receive(cx),
+            ReceiveQueue::Priority(queue) => queue.poll_receive(cx),
+        };
+        message.map(|message| self.received(message))
+    }
+
+    /// Polls whether a message is available, registering the task to be woken when one is.
+    pub fn poll_ready_to_receive(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match &self.receiver {
+            ReceiveQueue::Fifo(receiver) => receiver.poll_ready_to_receive(cx),
+            ReceiveQueue::Priority(queue) => queue.poll_ready_to_receive(cx),
+        }
+    }
+
+    fn received(&self, message: T) -> T {
+        if let Some(hook) = self.on_receive {
+            hook(&message);
+        }
+        message
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> From<Receiver<'static, T>> for Inbox<T> {
+    fn from(receiver: Receiver<'static, T>) -> Self {
+        Self {
+            receiver: ReceiveQueue::Fifo(receiver),
+            on_receive: None,
+        }
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T: 'static, const N: usize> From<&'static Channel<M, T, N>> for Inbox<T> {
+    fn from(channel: &'static Channel<M, T, N>) -> Self {
+        Self::from(Receiver::from(channel.receiver()))
+    }
+}
 
 #[cfg(target_os = "none")]
-pub type Inbox<T> = Receiver<'static, T>;
+impl<M: RawMutex, T: Prioritised + 'static, K: Kind, const N: usize>
+    From<&'static PriorityChannel<M, Queued<T>, K, N>> for Inbox<T>
+{
+    fn from(channel: &'static PriorityChannel<M, Queued<T>, K, N>) -> Self {
+        Self {
+            receiver: ReceiveQueue::Priority(channel),
+            on_receive: None,
+        }
+    }
+}
+
+/// The receiving end of an Agent's message queue.
+/// This is either a `tokio::sync::mpsc` queue, or one of the queues provided by `post_haste::queue`.
+#[cfg(not(target_os = "none"))]
+pub struct Inbox<T> {
+    inner: InboxInner<T>,
+    on_receive: Option<fn(&T)>,
+}
+
+#[cfg(not(target_os = "none"))]
+enum InboxInner<T> {
+    Mpsc(Receiver<T>),
+    Queue(QueueReceiver<T>),
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> Inbox<T> {
+    /// Sets a function to be called with each message as it is received.
+    /// `register_agent!()` uses this to measure the latency of messages.
+    pub fn with_receive_hook(self, hook: fn(&T)) -> Self {
+        Self {
+            on_receive: Some(hook),
+            ..self
+        }
+    }
+
+    /// Receives the next message, waiting until one is available.
+    /// Returns `None` once the queue is empty and can no longer receive messages.
+    pub async fn recv(&mut self) -> Option<T> {
+        let message = match &mut self.inner {
+            InboxInner::Mpsc(receiver) => receiver.recv().await,
+            InboxInner::Queue(receiver) => receiver.recv().await,
+        };
+        message.map(|message| self.received(message))
+    }
+
+    /// Receives the next message if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let message = match &mut self.inner {
+            InboxInner::Mpsc(receiver) => receiver.try_recv(),
+            InboxInner::Queue(receiver) => receiver.try_recv(),
+        };
+        message.map(|message| self.received(message))
+    }
+
+    fn received(&self, message: T) -> T {
+        if let Some(hook) = self.on_receive {
+            hook(&message);
+        }
+        message
+    }
+
+    /// Returns the number of messages waiting in the queue.
+    pub fn len(&self) -> usize {
+        match &self.inner {
+            InboxInner::Mpsc(receiver) => receiver.len(),
+            InboxInner::Queue(receiver) => receiver.len(),
+        }
+    }
+
+    /// Returns true if there are no messages waiting in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the queue, so that no further messages can be sent to it.
+    /// Messages already in the queue can still be received.
+    pub fn close(&mut self) {
+        match &mut self.inner {
+            InboxInner::Mpsc(receiver) => receiver.close(),
+            InboxInner::Queue(receiver) => receiver.close(),
+        }
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> From<Receiver<T>> for Inbox<T> {
+    fn from(receiver: Receiver<T>) -> Self {
+        Self {
+            inner: InboxInner::Mpsc(receiver),
+            on_receive: None,
+        }
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> From<QueueReceiver<T>> for Inbox<T> {
+    fn from(receiver: QueueReceiver<T>) -> Self {
+        Self {
+            inner: InboxInner::Queue(receiver),
+            on_receive: None,
+        }
+    }
+}
+
+/// The sending end of a message queue, as registered with the Postmaster.
+/// This is either a `tokio::sync::mpsc` queue, or one of the queues provided by `post_haste::queue`.
+#[cfg(not(target_os = "none"))]
+pub enum InboxSender<T> {
+    Mpsc(Sender<T>),
+    Queue(QueueSender<T>),
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> InboxSender<T> {
+    /// Sends a message, waiting until there is space in the queue.
+    pub async fn send(&self, message: T) -> Result<(), SendError<T>> {
+        match self {
+            Self::Mpsc(sender) => sender.send(message).await,
+            Self::Queue(sender) => sender.send(message).await,
+        }
+    }
+
+    /// Sends a message, waiting until there is space in the queue or the timeout expires.
+    /// Fails if the timeout expires or the receiver has been dropped, returning the message.
+    pub async fn send_timeout(
+        &self,
+        message: T,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<T>> {
+        match self {
+            Self::Mpsc(sender) => sender.send_timeout(message, timeout).await,
+            Self::Queue(sender) => sender.send_timeout(message, timeout).await,
+        }
+    }
+
+    /// Sends a message if there is space in the queue, without waiting.
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        match self {
+            Self::Mpsc(sender) => sender.try_send(message),
+            Self::Queue(sender) => sender.try_send(message),
+        }
+    }
+
+    /// Returns the number of messages waiting in the queue.
+    /// This is always known on tokio.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Self::Mpsc(sender) => Some(sender.max_capacity() - sender.capacity()),
+            Self::Queue(sender) => Some(sender.len()),
+        }
+    }
+
+    /// Returns true if there are no messages waiting in the queue, if known.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Returns a handle with which messages can be removed from the queue, if the queue supports it.
+    /// Only FIFO queues created with `post_haste::queue::fifo_channel()` support this.
+    pub fn evictor(&self) -> Option<&dyn Evict<T>> {
+        match self {
+            Self::Queue(sender) if sender.is_fifo() => Some(sender),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(not(target_os = "none"))]
-pub type Inbox<T> = Receiver<T>;
+impl<T> Clone for InboxSender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Mpsc(sender) => Self::Mpsc(sender.clone()),
+            Self::Queue(sender) => Self::Queue(sender.clone()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> From<Sender<T>> for InboxSender<T> {
+    fn from(sender: Sender<T>) -> Self {
+        Self::Mpsc(sender)
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T> From<QueueSender<T>> for InboxSender<T> {
+    fn from(sender: QueueSender<T>) -> Self {
+        Self::Queue(sender)
+    }
+}
+
+/// The sending end of a message queue, as registered with the Postmaster.
+/// When created from a reference to a `Channel`, the Postmaster is also able to remove messages from the queue, as required by some `OverflowPolicy`s.
+/// When created from a reference to a `Channel` or `PriorityChannel`, the number of messages in the queue is also available.
+/// A `PriorityChannel` must hold its messages as `Queued` items, which carry the key by which the queue orders them.
+#[cfg(target_os = "none")]
+pub struct InboxSender<T: 'static> {
+    sender: SendQueue<T>,
+    evictor: Option<&'static dyn Evict<T>>,
+    depth: Option<&'static dyn QueueDepth>,
+}
+
+#[cfg(target_os = "none")]
+enum SendQueue<T: 'static> {
+    Fifo(Sender<'static, T>),
+    Priority(&'static dyn PriorityQueue<T>),
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> SendQueue<T> {
+    fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        match self {
+            Self::Fifo(sender) => sender.try_send(message),
+            Self::Priority(queue) => queue.try_send(message),
+        }
+    }
+
+    fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Self::Fifo(sender) => sender.poll_ready_to_send(cx),
+            Self::Priority(queue) => queue.poll_ready_to_send(cx),
+        }
+    }
+}
+
+/// A queue which can report how many items it holds, independently of the item type.
+#[cfg(target_os = "none")]
+trait QueueDepth {
+    fn len(&self) -> usize;
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T, const N: usize> QueueDepth for Channel<M, T, N> {
+    fn len(&self) -> usize {
+        Channel::len(self)
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T: Ord, K: Kind, const N: usize> QueueDepth for PriorityChannel<M, T, K, N> {
+    fn len(&self) -> usize {
+        PriorityChannel::len(self)
+    }
+}
+
+/// A `PriorityChannel` of `Queued` items, independently of its mutex, kind and capacity.
+/// Items are wrapped as they are sent, and unwrapped as they are received.
+#[cfg(target_os = "none")]
+trait PriorityQueue<T> {
+    fn try_send(&self, item: T) -> Result<(), TrySendError<T>>;
+    fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()>;
+    fn try_receive(&self) -> Result<T, TryReceiveError>;
+    fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T>;
+    fn poll_ready_to_receive(&self, cx: &mut Context<'_>) -> Poll<()>;
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T: Prioritised, K: Kind, const N: usize> PriorityQueue<T>
+    for PriorityChannel<M, Queued<T>, K, N>
+{
+    fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        PriorityChannel::try_send(self, Queued::new(item))
+            .map_err(|TrySendError::Full(queued)| TrySendError::Full(queued.into_inner()))
+    }
+
+    fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        PriorityChannel::poll_ready_to_send(self, cx)
+    }
+
+    fn try_receive(&self) -> Result<T, TryReceiveError> {
+        PriorityChannel::try_receive(self).map(Queued::into_inner)
+    }
+
+    fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T> {
+        PriorityChannel::poll_receive(self, cx).map(Queued::into_inner)
+    }
+
+    fn poll_ready_to_receive(&self, cx: &mut Context<'_>) -> Poll<()> {
+        PriorityChannel::poll_ready_to_receive(self, cx)
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> InboxSender<T> {
+    /// Sends a message, waiting until there is space in the queue.
+    pub async fn send(&self, mut message: T) {
+        loop {
+            match self.sender.try_send(message) {
+                Ok(()) => return,
+                Err(TrySendError::Full(returned)) => message = returned,
+            }
+            poll_fn(|cx| self.sender.poll_ready_to_send(cx)).await;
+        }
+    }
+
+    /// Sends a message, waiting until there is space in the queue or the timeout expires.
+    /// If the timeout expires, the message is returned.
+    pub async fn send_timeout(&self, mut message: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.sender.try_send(message) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(returned)) => message = returned,
+            }
+            let ready = poll_fn(|cx| self.sender.poll_ready_to_send(cx));
+            if ready.with_deadline(deadline).await.is_err() {
+                return Err(message);
+            }
+        }
+    }
+
+    /// Sends a message if there is space in the queue, without waiting.
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send(message)
+    }
+
+    /// Returns the number of messages waiting in the queue, if known.
+    pub fn len(&self) -> Option<usize> {
+        self.depth.map(QueueDepth::len)
+    }
+
+    /// Returns true if there are no messages waiting in the queue, if known.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Returns a handle with which messages can be removed from the queue, if the queue supports it.
+    pub fn evictor(&self) -> Option<&dyn Evict<T>> {
+        self.evictor
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> Clone for InboxSender<T> {
+    fn clone(&self) -> Self {
+        let sender = match &self.sender {
+            SendQueue::Fifo(sender) => SendQueue::Fifo(sender.clone()),
+            SendQueue::Priority(queue) => SendQueue::Priority(*queue),
+        };
+        Self {
+            sender,
+            evictor: self.evictor,
+            depth: self.depth,
+        }
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<T: 'static> From<Sender<'static, T>> for InboxSender<T> {
+    fn from(sender: Sender<'static, T>) -> Self {
+        Self {
+            sender: SendQueue::Fifo(sender),
+            evictor: None,
+            depth: None,
+        }
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T: 'static, const N: usize> From<&'static Channel<M, T, N>> for InboxSender<T> {
+    fn from(channel: &'static Channel<M, T, N>) -> Self {
+        Self {
+            sender: SendQueue::Fifo(channel.sender().into()),
+            evictor: Some(channel),
+            depth: Some(channel),
+        }
+    }
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T: Prioritised + 'static, K: Kind, const N: usize>
+    From<&'static PriorityChannel<M, Queued<T>, K, N>> for InboxSender<T>
+{
+    fn from(channel: &'static PriorityChannel<M, Queued<T>, K, N>) -> Self {
+        Self {
+            sender: SendQueue::Priority(channel),
+            evictor: None,
+            depth: Some(channel),
+        }
+    }
+}
 
 #[allow(async_fn_in_trait)]
 pub trait Agent {
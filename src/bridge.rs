@@ -0,0 +1,336 @@
+//! A bridge which links the Postmasters on two devices over a serial stream, such as a UART, enabled by the `bridge` feature.
+//! Each device registers a `Bridge` Agent, along with a routing table of the addresses whose Agents live on the other device.
+//! Messages sent to those addresses are forwarded to the Bridge, which passes them across the stream to the Bridge on the other device, which then delivers them to their destination.
+//! Agents on either device can therefore address each other exactly as they would if they were on the same device.
+//!
+//! Each message is encoded with postcard along with its destination, followed by a CRC-16 of the encoded bytes.
+//! The result is COBS-encoded, so that it contains no zero bytes, and sent as a frame terminated by a zero byte.
+//! A frame which is corrupted in transit fails its CRC and is dropped, and the receiver picks up again from the next zero byte.
+//! Both devices must use the same address and payload enums, which must implement serde's `Serialize` and `Deserialize`.
+use core::future::pending;
+use core::marker::PhantomData;
+
+use crc::{CRC_16_IBM_3740, Crc};
+use embassy_futures::join::join;
+use embedded_io_async::{Read, Write};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+#[cfg(not(target_os = "none"))]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::PostmasterError;
+use crate::agent::{Agent, Inbox};
+
+/// The maximum length of a frame sent or received by a `Bridge`, unless another is given as its final type parameter.
+pub const DEFAULT_FRAME_SIZE: usize = 256;
+
+/// The checksum appended to each frame before it is COBS-encoded.
+const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+const CRC_SIZE: usize = 2;
+
+/// Gives the `Bridge` access to a Postmaster generated by `init_postmaster!()`, whose addresses are of type `A`.
+/// This trait is implemented for the generated `postmaster::Message` type, and should not need to be implemented by hand.
+#[allow(async_fn_in_trait)]
+pub trait BridgeMessage<A>: Sized {
+    /// The address to which the message was delivered (see `postmaster::Message::destination()`).
+    fn destination(&self) -> Option<A>;
+
+    /// Delivers messages sent to `address` to the mailbox registered to `via` (see `postmaster::forward()`).
+    async fn forward(address: A, via: A) -> Result<(), PostmasterError>;
+
+    /// Sends a message received from the other device to its destination, keeping its source, priority and payload.
+    /// The message is stamped by the local Postmaster as it is sent, as any other message would be.
+    async fn inject(destination: A, message: Self) -> Result<(), PostmasterError>;
+}
+
+/// The configuration of a `Bridge`.
+pub struct BridgeConfig<A: 'static, R, W> {
+    /// The stream from which frames sent by the other device are read
+    pub reader: R,
+    /// The stream to which frames are written for the other device
+    pub writer: W,
+    /// The addresses whose Agents live on the other device
+    pub remote: &'static [A],
+}
+
+/// An Agent which links the Postmaster on this device to the Postmaster on another device, over a serial stream.
+///
+/// When the Bridge starts running, each of the remote addresses in its config is forwarded to the Bridge's own address, so any message sent to a remote address is received by the Bridge and passed to the other device.
+/// Messages received from the other device are sent to their destination through the local Postmaster.
+/// A message which cannot be delivered locally is handled as any other would be (e.g. by being forwarded to the dead-letter address), but the Agent on the other device which sent it is not told.
+/// Messages sent to a remote address before the Bridge starts running fail with `NoRecipient`, unless the address has already been forwarded to the Bridge with `postmaster::forward()`.
+///
+/// Frames which are corrupt, or too long for the Bridge's buffers, are dropped.
+/// So are frames whose destination is one of this device's remote addresses, so that a message can never pass back and forth between the devices.
+/// `FRAME_SIZE` is the length of the Bridge's buffers, which bounds the size of a frame (including its CRC, COBS overhead and terminating zero byte).
+/// Both devices should use the same frame size.
+///
+/// On tokio, streams are adapted for the Bridge with `FromTokio`, which allows a Bridge to be tested with an in-memory stream (`tokio::io::duplex()`) or a pseudo-terminal.
+///
+/// # Examples
+/// ```ignore
+/// static REMOTE: [Addresses; 2] = [Addresses::Motor, Addresses::Encoder];
+///
+/// let (reader, writer) = uart.split();
+/// let config = BridgeConfig { reader, writer, remote: &REMOTE };
+/// postmaster::register_agent!(spawner, Bridge, Bridge<Addresses, postmaster::Message, BufferedUarteRx, BufferedUarteTx>, config, 8).unwrap();
+/// ```
+pub struct Bridge<A: 'static, M, R, W, const FRAME_SIZE: usize = DEFAULT_FRAME_SIZE> {
+    address: A,
+    config: BridgeConfig<A, R, W>,
+    message: PhantomData<fn() -> M>,
+}
+
+impl<A, M, R, W, const FRAME_SIZE: usize> Agent for Bridge<A, M, R, W, FRAME_SIZE>
+where
+    A: Copy + PartialEq + Serialize + DeserializeOwned + 'static,
+    M: BridgeMessage<A> + Serialize + DeserializeOwned + 'static,
+    R: Read,
+    W: Write,
+{
+    type Address = A;
+    type Message = M;
+    type Config = BridgeConfig<A, R, W>;
+
+    async fn create(address: Self::Address, config: Self::Config) -> Self {
+        Self {
+            address,
+            config,
+            message: PhantomData,
+        }
+    }
+
+    async fn run(self, inbox: Inbox<Self::Message>) -> ! {
+        let BridgeConfig {
+            reader,
+            writer,
+            remote,
+        } = self.config;
+        for &address in remote {
+            // The Bridge's mailbox is registered before it runs, so forwarding to it cannot fail
+            let _ = M::forward(address, self.address).await;
+        }
+        let (never, _) = join(
+            send_frames::<A, M, W, FRAME_SIZE>(inbox, writer, remote),
+            receive_frames::<A, M, R, FRAME_SIZE>(reader, remote),
+        )
+        .await;
+        never
+    }
+}
+
+/// Passes the messages received by the Bridge to the other device, provided they were sent to a remote address.
+async fn send_frames<A, M, W, const FRAME_SIZE: usize>(
+    #[cfg_attr(target_os = "none", allow(unused_mut))] mut inbox: Inbox<M>,
+    mut writer: W,
+    remote: &[A],
+) -> !
+where
+    A: Copy + PartialEq + Serialize + 'static,
+    M: BridgeMessage<A> + Serialize + 'static,
+    W: Write,
+{
+    let mut contents = [0; FRAME_SIZE];
+    let mut frame = [0; FRAME_SIZE];
+    loop {
+        #[cfg(not(target_os = "none"))]
+        let Some(message) = inbox.recv().await else {
+            // The Bridge's mailbox is no longer registered, so no more messages will arrive
+            loop {
+                pending::<()>().await;
+            }
+        };
+        #[cfg(target_os = "none")]
+        let message = inbox.receive().await;
+        // Messages sent to the Bridge's own address are not passed on
+        let Some(destination) = message
+            .destination()
+            .filter(|destination| remote.contains(destination))
+        else {
+            continue;
+        };
+        let Some(length) = encode_frame(&destination, &message, &mut contents, &mut frame) else {
+            continue;
+        };
+        // As with a message lost to noise on the line, a message which cannot be written is dropped
+        if writer.write_all(&frame[..length]).await.is_ok() {
+            let _ = writer.flush().await;
+        }
+    }
+}
+
+/// Reads frames from the other device, and sends the messages they contain to their destinations.
+async fn receive_frames<A, M, R, const FRAME_SIZE: usize>(mut reader: R, remote: &[A]) -> !
+where
+    A: Copy + PartialEq + DeserializeOwned,
+    M: BridgeMessage<A> + DeserializeOwned,
+    R: Read,
+{
+    let mut buffer = [0; FRAME_SIZE];
+    let mut filled = 0;
+    // Set while skipping the remainder of a frame which has been dropped
+    let mut skipping = false;
+    loop {
+        if filled == FRAME_SIZE {
+            filled = 0;
+            skipping = true;
+        }
+        let read = match reader.read(&mut buffer[filled..]).await {
+            Ok(read) if read > 0 => read,
+            // The stream has ended, so no more frames will arrive
+            Ok(_) => loop {
+                pending::<()>().await;
+            },
+            // An error (e.g. a framing error on a UART) may have corrupted the frame being received
+            Err(_) => {
+                filled = 0;
+                skipping = true;
+                continue;
+            }
+        };
+        let mut start = 0;
+        let mut scanned = filled;
+        filled += read;
+        while let Some(offset) = buffer[scanned..filled].iter().position(|byte| *byte == 0) {
+            let end = scanned + offset;
+            if !skipping
+                && let Some((destination, message)) = decode_frame::<A, M>(&mut buffer[start..end])
+                && !remote.contains(&destination)
+            {
+                // A message which cannot be delivered is handled by the local Postmaster
+                let _ = M::inject(destination, message).await;
+            }
+            skipping = false;
+            start = end + 1;
+            scanned = start;
+        }
+        buffer.copy_within(start..filled, 0);
+        filled -= start;
+    }
+}
+
+/// Encodes a message and its destination into a frame, returning the length of the frame (including the zero byte which ends it).
+/// Returns `None` if the frame would not fit in `frame`.
+fn encode_frame<A: Serialize, M: Serialize>(
+    destination: &A,
+    message: &M,
+    contents: &mut [u8],
+    frame: &mut [u8],
+) -> Option<usize> {
+    let capacity = contents.len().checked_sub(CRC_SIZE)?;
+    let length = postcard::to_slice(&(destination, message), &mut contents[..capacity])
+        .ok()?
+        .len();
+    let crc = CRC.checksum(&contents[..length]);
+    contents[length..length + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    // Space is left for the terminating zero byte
+    let limit = frame.len().checked_sub(1)?;
+    let encoded = cobs::try_encode(&contents[..length + CRC_SIZE], &mut frame[..limit]).ok()?;
+    frame[encoded] = 0;
+    Some(encoded + 1)
+}
+
+/// Decodes a frame received from the other device (without the zero byte which ended it), returning the destination and message it contains.
+/// Returns `None` if the frame is corrupt, or was not sent by a Bridge using the same address and payload enums.
+fn decode_frame<A: DeserializeOwned, M: DeserializeOwned>(frame: &mut [u8]) -> Option<(A, M)> {
+    let length = cobs::decode_in_place(frame).ok()?;
+    let (contents, crc) = frame[..length].split_at(length.checked_sub(CRC_SIZE)?);
+    if CRC.checksum(contents).to_le_bytes() != crc {
+        return None;
+    }
+    postcard::from_bytes(contents).ok()
+}
+
+/// Adapts a tokio stream for use by a `Bridge`, e.g. one end of `tokio::io::duplex()` or a pseudo-terminal (tokio only).
+#[cfg(not(target_os = "none"))]
+pub struct FromTokio<T>(pub T);
+
+#[cfg(not(target_os = "none"))]
+impl<T> embedded_io_async::ErrorType for FromTokio<T> {
+    type Error = std::io::Error;
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T: AsyncRead + Unpin> Read for FromTokio<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<T: AsyncWrite + Unpin> Write for FromTokio<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    enum Address {
+        Controller,
+        Motor,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Payload {
+        SetSpeed(u16),
+        Log(Vec<u8>),
+    }
+
+    /// Encodes a frame, returning it without the zero byte which ends it.
+    fn encode(destination: Address, payload: &Payload) -> Option<Vec<u8>> {
+        let mut contents = [0; DEFAULT_FRAME_SIZE];
+        let mut frame = [0; DEFAULT_FRAME_SIZE];
+        let length = encode_frame(&destination, payload, &mut contents, &mut frame)?;
+        assert_eq!(frame[length - 1], 0);
+        Some(frame[..length - 1].to_vec())
+    }
+
+    #[test]
+    fn frame_round_trips() {
+        // Zero bytes within the message must not end the frame early
+        for payload in [Payload::SetSpeed(1200), Payload::Log(vec![0, 1, 0, 0, 2])] {
+            let mut frame = encode(Address::Motor, &payload).unwrap();
+            assert!(!frame.contains(&0));
+            assert_eq!(
+                decode_frame::<Address, Payload>(&mut frame),
+                Some((Address::Motor, payload))
+            );
+        }
+    }
+
+    #[test]
+    fn frame_with_a_corrupted_byte_is_rejected() {
+        let frame = encode(Address::Controller, &Payload::SetSpeed(2400)).unwrap();
+        for index in 0..frame.len() {
+            let mut corrupted = frame.clone();
+            corrupted[index] ^= 0x10;
+            assert_eq!(decode_frame::<Address, Payload>(&mut corrupted), None);
+        }
+    }
+
+    #[test]
+    fn frame_which_is_too_short_is_rejected() {
+        let frame = encode(Address::Controller, &Payload::SetSpeed(2400)).unwrap();
+        assert_eq!(decode_frame::<Address, Payload>(&mut []), None);
+        assert_eq!(
+            decode_frame::<Address, Payload>(&mut frame[..2].to_vec()),
+            None
+        );
+    }
+
+    #[test]
+    fn message_too_large_for_the_frame_is_not_encoded() {
+        let payload = Payload::Log(vec![1; DEFAULT_FRAME_SIZE]);
+        assert_eq!(encode(Address::Motor, &payload), None);
+    }
+}
@@ -11,8 +11,11 @@ pub mod imports {
 
 use imports::*;
 
+use crate::VariantIndex;
+
 /// Enumeration of potential errors which the Postmaster may encounter
-#[derive(Debug)]
+/// Each variant is indexed using `VariantIndex`, so that the Postmaster's diagnostics can count failures of each kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantIndex)]
 pub enum PostmasterError {
     /// The address specified has already been assigned
     AddressAlreadyTaken,
@@ -29,8 +32,8 @@ pub enum PostmasterError {
     /// Calling `try_send()` on the recipient's message queue failed.
     /// This is most likely due to teh recipient's message queue being full.
     TrySendFailed,
-    /// Postmaster was unable to spawn a task to handle the delayed message.
-    /// This is most likely caused by the task pool being too small.
+    /// Postmaster was unable to schedule the delayed message.
+    /// This is most likely caused by the delayed message pool being full.
     /// Try increasing the DELAYED_MESSAGE_POOL_SIZE environment variable (default is 8).
     #[cfg(target_os = "none")]
     DelayedMessagePoolFull,
@@ -39,6 +42,63 @@ pub enum PostmasterError {
     /// If you have not yet registered any Agents, you can call `postmaster::set_spawner()` before attempting to send the delayed message.
     #[cfg(target_os = "none")]
     SpawnerNotSet,
+    /// The recipient of a request dropped its `ReplyToken` without replying.
+    #[cfg(not(target_os = "none"))]
+    NoReply, // Tokio Specific
+    /// A reply could not be delivered because the requester is no longer waiting for it.
+    /// This is most likely because the request timed out before the reply was sent.
+    RequestExpired,
+    /// Postmaster was unable to reserve a slot to hold the reply to a request.
+    /// This is caused by too many requests awaiting replies at the same time.
+    /// Try increasing the REPLY_SLOT_POOL_SIZE environment variable (default is 4).
+    #[cfg(target_os = "none")]
+    ReplySlotPoolFull,
+    /// The delayed message could not be cancelled or rescheduled, as it has already been delivered (or cancelled).
+    DelayedMessageNotPending,
+    /// The overflow policy requires the Postmaster to be able to remove messages from the mailbox's queue, which the mailbox does not support.
+    /// The `DropOldest` and `OverwriteLatest` policies are not supported by priority queues, or by mailboxes registered using only their sender.
+    OverflowPolicyUnsupported,
+    /// An interceptor set with `postmaster::set_interceptors()` rejected the message.
+    Rejected,
+    /// A payload in the trace passed to `postmaster::replay()` could not be decoded.
+    #[cfg(not(target_os = "none"))]
+    UndecodablePayload, // Tokio Specific
+}
+
+/// The error returned when the Postmaster fails to deliver a message.
+/// Unlike a bare `PostmasterError`, this hands the undelivered message back, so that it can be retried or rerouted without the payload having been cloned up-front.
+/// The generated `postmaster` module provides an alias for this type, `postmaster::SendError`, with the project's message and address types filled in.
+pub struct SendError<T, A> {
+    /// The reason the message could not be delivered
+    pub error: PostmasterError,
+    /// The address to which the message could not be delivered (after any rerouting by interceptors)
+    pub destination: A,
+    /// The undelivered message.
+    /// This is `None` if the message was forwarded to the Postmaster's dead-letter address instead.
+    pub message: Option<T>,
+}
+
+impl<T, A> SendError<T, A> {
+    /// Take the undelivered message back out of the error, if it is still held.
+    pub fn into_message(self) -> Option<T> {
+        self.message
+    }
+}
+
+// Implemented by hand so that neither the message nor the address type needs to implement `Debug`
+impl<T, A> core::fmt::Debug for SendError<T, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SendError")
+            .field("error", &self.error)
+            .field("message_returned", &self.message.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, A> From<SendError<T, A>> for PostmasterError {
+    fn from(error: SendError<T, A>) -> Self {
+        error.error
+    }
 }
 
 impl From<TryLockError> for PostmasterError {
@@ -68,8 +128,8 @@ impl From<SpawnError> for PostmasterError {
 }
 
 #[cfg(not(target_os = "none"))]
-impl<T> From<SendError<T>> for PostmasterError {
-    fn from(_: SendError<T>) -> Self {
+impl<T> From<imports::SendError<T>> for PostmasterError {
+    fn from(_: imports::SendError<T>) -> Self {
         Self::ReceiverClosed
     }
 }
@@ -0,0 +1,116 @@
+//! Measurement of the time messages take to reach their recipients.
+//! Latency tracking is opt-in, and is enabled with `postmaster::set_latency_tracking()`.
+use portable_atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::dependencies::Duration;
+
+/// The number of buckets in a latency histogram.
+/// The first bucket counts latencies under 1 us, and each bucket after that covers twice the range of the one before it, from 2^(index-1) us up to 2^index us.
+/// The final bucket counts every latency of 2^14 us (16.384 ms) or more.
+pub const LATENCY_BUCKET_COUNT: usize = 16;
+
+/// Latency statistics for the messages received at a single address.
+/// Latency is measured from when a message is sent to when it is received from the recipient's inbox, so includes any time spent waiting for space in (or sitting in) the recipient's queue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// The number of messages measured
+    pub count: usize,
+    /// The shortest latency measured, or zero if no messages have been measured
+    pub min: Duration,
+    /// The longest latency measured
+    pub max: Duration,
+    /// The mean latency, or zero if no messages have been measured
+    pub mean: Duration,
+    /// The number of messages measured in each bucket (see `LATENCY_BUCKET_COUNT` for the range of each bucket)
+    pub histogram: [usize; LATENCY_BUCKET_COUNT],
+}
+
+impl LatencyStats {
+    /// Returns the range of latencies counted by the given histogram bucket, from its inclusive lower bound to its exclusive upper bound.
+    /// The final bucket has no upper bound.
+    pub fn bucket_range(index: usize) -> (Duration, Option<Duration>) {
+        let lower = match index {
+            0 => 0,
+            _ => 1 << (index - 1),
+        };
+        let upper = (index < LATENCY_BUCKET_COUNT - 1).then(|| Duration::from_micros(1 << index));
+        (Duration::from_micros(lower), upper)
+    }
+}
+
+/// Accumulates the latency statistics for a single address.
+/// Used by the Postmaster generated by `init_postmaster!()`.
+#[doc(hidden)]
+pub struct LatencyRecorder {
+    count: AtomicUsize,
+    total_us: AtomicU64,
+    min_us: AtomicU32,
+    max_us: AtomicU32,
+    histogram: [AtomicUsize; LATENCY_BUCKET_COUNT],
+}
+
+impl LatencyRecorder {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            total_us: AtomicU64::new(0),
+            min_us: AtomicU32::new(u32::MAX),
+            max_us: AtomicU32::new(0),
+            histogram: [const { AtomicUsize::new(0) }; LATENCY_BUCKET_COUNT],
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let latency_us = as_micros(latency);
+        let bucket =
+            ((u64::BITS - latency_us.leading_zeros()) as usize).min(LATENCY_BUCKET_COUNT - 1);
+        let clamped_us = u32::try_from(latency_us).unwrap_or(u32::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(latency_us, Ordering::Relaxed);
+        self.min_us.fetch_min(clamped_us, Ordering::Relaxed);
+        self.max_us.fetch_max(clamped_us, Ordering::Relaxed);
+        self.histogram[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let count = self.count.load(Ordering::Relaxed);
+        let total_us = self.total_us.load(Ordering::Relaxed);
+        let (min_us, mean_us) = match count {
+            0 => (0, 0),
+            _ => (self.min_us.load(Ordering::Relaxed), total_us / count as u64),
+        };
+        LatencyStats {
+            count,
+            min: Duration::from_micros(min_us.into()),
+            max: Duration::from_micros(self.max_us.load(Ordering::Relaxed).into()),
+            mean: Duration::from_micros(mean_us),
+            histogram: core::array::from_fn(|index| self.histogram[index].load(Ordering::Relaxed)),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.total_us.store(0, Ordering::Relaxed);
+        self.min_us.store(u32::MAX, Ordering::Relaxed);
+        self.max_us.store(0, Ordering::Relaxed);
+        for bucket in &self.histogram {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(target_os = "none")]
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_micros()
+}
@@ -1,33 +1,78 @@
 #![cfg_attr(target_os = "none", no_std)]
 
+// Allows `#[derive(VariantIndex)]` to be used within this crate
+extern crate self as post_haste;
+
 pub mod agent;
+#[cfg(feature = "bridge")]
+pub mod bridge;
 pub mod error;
+pub mod latency;
+pub mod overflow;
+pub mod priority;
+#[cfg(not(target_os = "none"))]
+pub mod queue;
+#[cfg(not(target_os = "none"))]
+pub mod recording;
+pub mod retry;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(not(target_os = "none"))]
+pub mod supervisor;
+#[cfg(not(target_os = "none"))]
+pub mod testing;
+#[cfg(all(test, not(target_os = "none")))]
+mod tests;
+pub mod variant_index;
 
 #[cfg(not(target_os = "none"))]
 pub mod async_runtime_dependencies {
+    pub use crate::agent::InboxSender;
+    pub use crate::queue::{fifo_channel, priority_channel};
+    pub use crate::recording::{Record, Recorder};
+    pub use core::future::Future;
     pub use once_cell::sync::Lazy;
+    pub use std::sync::{Arc, Weak};
     pub use tokio::sync::Mutex;
+    pub use tokio::sync::mpsc::error::SendTimeoutError;
     pub use tokio::sync::mpsc::{Receiver, Sender, channel};
+    pub use tokio::sync::{Notify, oneshot};
     pub use tokio::task;
+    pub use tokio::task_local;
     pub use tokio::time;
-    pub use tokio::time::Duration;
+    pub use tokio::time::{Duration, Instant};
 }
 #[cfg(target_os = "none")]
 pub mod async_runtime_dependencies {
+    pub use crate::agent::InboxSender;
     pub use embassy_executor::{SpawnToken, Spawner, task};
     pub use embassy_sync::{
         blocking_mutex::raw::NoopRawMutex,
-        channel::{Channel, DynamicSender},
+        channel::Channel,
         mutex::Mutex,
+        priority_channel::{Max, PriorityChannel},
+        signal::Signal,
     };
-    pub use embassy_time::{Duration, Timer, WithTimeout};
+    pub use embassy_time::{Duration, Instant, Timer, WithTimeout};
 }
 pub mod dependencies {
     pub use crate::async_runtime_dependencies::*;
+    pub use crate::error::imports::TrySendError;
+    pub use crate::latency::LatencyRecorder;
+    pub use crate::overflow::Evict;
+    pub use crate::retry::Retry;
+    pub use crate::variant_index::VariantIndex;
     pub use const_env::env_item;
-    pub use portable_atomic::{AtomicU32, AtomicUsize};
+    pub use portable_atomic::{AtomicBool, AtomicU32, AtomicUsize};
 }
 pub use error::PostmasterError;
+pub use post_haste_macros::VariantIndex;
+pub use variant_index::VariantIndex;
+
+/// The topic type used by the Postmaster when no topic enum is passed to `init_postmaster!()`.
+/// As this enum has no variants, it is not possible to subscribe or publish to any topics.
+#[derive(VariantIndex)]
+pub enum NoTopics {}
 
 /// Initialise the Postmaster for use in your project.
 /// As the code for the Postmaster is no_std, it requires information about the project.
@@ -35,18 +80,20 @@ pub use error::PostmasterError;
 /// This macro requires two arguments: an enum type defining the Agent addresses, and an enum type defining the message payloads.
 /// An optional third argument allows the setting of the default timeout (in microseconds) used when attempting to send a message.
 /// If this third argument is omitted, a timeout of 1 ms (1000 us) will be used.
+/// An optional argument of the form `topics = crate::Topic` provides an enum of topics which Agents can subscribe and publish to.
+/// Due to the way the macro expands, the topic enum must be given with its full path.
+/// A final optional argument of the form `dead_letter = Address::DeadLetters` gives an address to which the Postmaster forwards any message which it cannot deliver.
 /// The output of the macro is the `postmaster` module, which contains the API for the Postmaster.
 ///
 /// # Notes
-/// The logic generated by this macro relies on the (currently) unstable feature `variant_count`.
-/// Therefore the project must be built with the nightly compiler and you will need to include this feature at the top of the file (see example).
+/// The Postmaster sizes its tables of addresses and topics at compile time, so the address and topic enums must implement the `VariantIndex` trait.
+/// This is done by deriving it with `#[derive(VariantIndex)]` (see example).
 ///
 /// # Examples
 /// ```rust
-/// #![feature(variant_count)]
-///
-/// use post_haste::init_postmaster;
+/// use post_haste::{VariantIndex, init_postmaster};
 ///
+/// #[derive(Clone, Copy, VariantIndex)]
 /// enum Address {
 ///   AgentOne,
 ///   AgentTwo,
@@ -59,13 +106,20 @@ pub use error::PostmasterError;
 ///   // ...
 /// }
 ///
-/// init_postmaster!(Address, Payloads);
+/// #[derive(VariantIndex)]
+/// enum Topic {
+///   SystemMode,
+///   // ...
+/// }
+///
+/// init_postmaster!(Address, Payloads, topics = crate::Topic);
+/// # fn main() {}
 /// ```
 #[macro_export]
 #[allow(clippy::crate_in_macro_def)]
 macro_rules! init_postmaster {
 
-    ($address_enum:ty, $payload_enum:ty, $timeout_us: expr) => {
+    (@postmaster $address_enum:ty, $payload_enum:ty, $timeout_us: expr, $topic_enum:ty, $dead_letter_address:expr) => {
         /// API module for the Postmaster
         /// This module contains all of the functions required to pass messages between Agents, facilitated by the Postmaster.
         ///
@@ -77,7 +131,29 @@ macro_rules! init_postmaster {
             use post_haste::PostmasterError;
             use post_haste::dependencies::*;
 
-            const ADDRESS_COUNT: usize = core::mem::variant_count::<$address_enum>();
+            const ADDRESS_COUNT: usize = <$address_enum as VariantIndex>::VARIANT_COUNT;
+            const TOPIC_COUNT: usize = <Topic as VariantIndex>::VARIANT_COUNT;
+            const ERROR_COUNT: usize = <PostmasterError as VariantIndex>::VARIANT_COUNT;
+            /// The address to which undeliverable messages are forwarded, as passed to `init_postmaster!()`.
+            const DEAD_LETTER_ADDRESS: Option<$address_enum> = $dead_letter_address;
+
+            /// The topics which Agents can subscribe and publish to, as passed to `init_postmaster!()`.
+            pub type Topic = $topic_enum;
+
+            pub use post_haste::latency::LatencyStats;
+            pub use post_haste::overflow::OverflowPolicy;
+            pub use post_haste::priority::Priority;
+            pub use post_haste::retry::RetryPolicy;
+
+            /// The error returned when a message cannot be delivered, handing back the undelivered message along with the reason and its destination.
+            pub type SendError = post_haste::error::SendError<Message, $address_enum>;
+
+            #[cfg(not(target_os = "none"))]
+            pub use post_haste::recording::RecordingTarget;
+
+            /// A recording of the messages delivered by the Postmaster, made with `postmaster::start_recording()` (tokio only).
+            #[cfg(not(target_os = "none"))]
+            pub type Trace = post_haste::recording::Trace<$address_enum>;
 
             /// Initialises an Agent and its message queue
             /// This macro both instantiates an Actor and kicks off its main loop.
@@ -85,27 +161,101 @@ macro_rules! init_postmaster {
             /// As well as the address and Agent type this macro also requires an instance of the Agent's associated Config type which is used during the instantiation of the Agent, and an optional queue size parameter which dictates the number of messages the Agent's message queue can hold.
             /// If no queue size parameter is given this defaults to 1, meaning that if there is already a message waiting in an Agent's queue then any attempt to send a message to the Agent will have to wait until either the queued message is received, or the send timeout is reached (in which case message sending is considered a failure).
             /// If try_send() is used to send to a full message queue, it will immediately return with failure.
+            /// The queue size may instead be given as `priority_queue = <size>`, in which case the Agent receives its messages highest priority first (see `Priority`).
+            /// An overflow policy may also be given, e.g. `overflow = OverflowPolicy::DropOldest`, to determine what happens when a message is sent while the queue is full (see `OverflowPolicy`).
+            /// Alternatively, `watch` may be given in place of the queue size, in which case the Agent's mailbox only holds the most recent message sent to it (a queue of 1 with `OverflowPolicy::OverwriteLatest`).
+            /// A restart strategy (`post_haste::supervisor::RestartStrategy`) may be given as a final argument (after any overflow policy), e.g. `restart = RestartStrategy::OneForOne`, in which case the Agent is supervised.
+            /// If a supervised Agent panics, it is re-created using its original config (which must therefore implement Clone) and given a fresh message queue at the same address.
+            /// Panics and restarts are counted in the Postmaster's diagnostics.
             #[macro_export]
             #[cfg(not(target_os = "none"))]
             macro_rules! _register_agent {
-                ($agent_address:ident, $agent:ty, $config:expr, $queue_size: expr) => {{
-                    use crate::postmaster::Message;
+                (@unsupervised $agent_address:ident, $agent:ty, $config:expr, $queue_size:expr, $prioritised:literal, $overflow:expr) => {{
                     use post_haste::agent::Agent;
-                    use post_haste::dependencies::*;
-                    struct Mailbox {
-                        pub sender: Sender<Message>,
-                        pub receiver: Receiver<Message>,
-                    }
-                    let (sender, receiver) = channel::<Message>($queue_size);
+                    let overflow = $overflow;
+                    let (sender, receiver) = postmaster::agent_mailbox($queue_size, $prioritised, overflow);
 
                     let agent = <$agent>::create(<$address_enum>::$agent_address, $config).await;
-                    postmaster::register(<$address_enum>::$agent_address, sender).await.inspect(|_|{
+                    postmaster::register(<$address_enum>::$agent_address, sender, overflow).await.inspect(|_|{
 
-                        tokio::task::spawn(async move {
+                        postmaster::spawn(async move {
                             agent.run(receiver).await;
                         });
                     })
                 }};
+                (@supervised $agent_address:ident, $agent:ty, $config:expr, $queue_size:expr, $prioritised:literal, $overflow:expr, $strategy:expr) => {{
+                    use post_haste::agent::Agent;
+                    use post_haste::supervisor::Supervisor;
+                    let address = <$address_enum>::$agent_address;
+                    let config = $config;
+                    let overflow = $overflow;
+                    let (sender, receiver) = postmaster::agent_mailbox($queue_size, $prioritised, overflow);
+
+                    let agent = <$agent>::create(address, Clone::clone(&config)).await;
+                    postmaster::register(address, sender, overflow).await.inspect(|_| {
+                        let mut supervisor = Supervisor::new($strategy);
+                        postmaster::spawn(async move {
+                            let (mut agent, mut receiver) = (agent, receiver);
+                            loop {
+                                let Err(error) = postmaster::spawn(agent.run(receiver)).await;
+                                if !error.is_panic() {
+                                    // The runtime is shutting down
+                                    return;
+                                }
+                                let restart = supervisor.should_restart();
+                                postmaster::report_agent_panic(restart);
+                                if !restart {
+                                    let _ = postmaster::unregister(address).await;
+                                    return;
+                                }
+                                let (sender, new_receiver) = postmaster::agent_mailbox($queue_size, $prioritised, overflow);
+                                agent = <$agent>::create(address, Clone::clone(&config)).await;
+                                receiver = new_receiver;
+                                // The policy was accepted when the Agent was first registered, so cannot be rejected now
+                                let _ = postmaster::replace(address, sender, overflow).await;
+                            }
+                        });
+                    })
+                }};
+                ($agent_address:ident, $agent:ty, $config:expr, watch, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, 1, false, post_haste::overflow::OverflowPolicy::OverwriteLatest, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, watch) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, 1, false, post_haste::overflow::OverflowPolicy::OverwriteLatest)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size:expr, overflow = $overflow:expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, $queue_size, true, $overflow, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size:expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, $queue_size, true, $overflow)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size:expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, $queue_size, true, post_haste::overflow::OverflowPolicy::Block, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size:expr) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, $queue_size, true, post_haste::overflow::OverflowPolicy::Block)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, overflow = $overflow:expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, 1, false, $overflow, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, 1, false, $overflow)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, 1, false, post_haste::overflow::OverflowPolicy::Block, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, $queue_size: expr, overflow = $overflow:expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, $queue_size, false, $overflow, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, $queue_size: expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, $queue_size, false, $overflow)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, $queue_size: expr, restart = $strategy:expr) => {
+                    crate::postmaster::register_agent!(@supervised $agent_address, $agent, $config, $queue_size, false, post_haste::overflow::OverflowPolicy::Block, $strategy)
+                };
+                ($agent_address:ident, $agent:ty, $config:expr, $queue_size: expr) => {
+                    crate::postmaster::register_agent!(@unsupervised $agent_address, $agent, $config, $queue_size, false, post_haste::overflow::OverflowPolicy::Block)
+                };
                 ($agent_address:ident, $agent:ty, $config:expr) => {
                     crate::postmaster::register_agent!($agent_address, $agent, $config, 1)
                 };
@@ -117,31 +267,60 @@ macro_rules! init_postmaster {
             /// As well as the address and Agent type this macro also requires an instance of the Agent's associated Config type which is used during the instantiation of the Agent, and an optional queue size parameter which dictates the number of messages the Agent's message queue can hold.
             /// If no queue size parameter is given this defaults to 1, meaning that if there is already a message waiting in an Agent's queue then any attempt to send a message to the Agent will have to wait until either the queued message is received, or the send timeout is reached (in which case message sending is considered a failure).
             /// If try_send() is used to send to a full message queue, it will immediately return with failure.
+            /// The queue size may instead be given as `priority_queue = <size>`, in which case the Agent receives its messages highest priority first (see `Priority`).
+            /// An overflow policy may also be given, e.g. `overflow = OverflowPolicy::DropOldest`, to determine what happens when a message is sent while the queue is full (see `OverflowPolicy`).
+            /// Alternatively, `watch` may be given in place of the queue size, in which case the Agent's mailbox only holds the most recent message sent to it (a queue of 1 with `OverflowPolicy::OverwriteLatest`).
             #[macro_export]
             #[cfg(target_os = "none")]
             macro_rules! _register_agent {
-                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, $queue_size: expr) => {{
-                    use post_haste::dependencies::{NoopRawMutex, Channel, task};
+                (@register $spawner:ident, $agent_address:ident, $agent:ty, $config:expr, $queue:ty, $overflow:expr) => {{
+                    use post_haste::dependencies::task;
                     use post_haste::agent::Agent;
-                    use crate::postmaster::Message;
                     struct Mailbox {
-                        pub inner: Channel<NoopRawMutex, Message, $queue_size>
+                        pub inner: $queue
                     }
 
                     unsafe impl Sync for Mailbox{}
-                    static MAILBOX: Mailbox = Mailbox{ inner: Channel::new()};
+                    static MAILBOX: Mailbox = Mailbox{ inner: <$queue>::new()};
 
                     let agent = <$agent>::create(<$address_enum>::$agent_address, $config).await;
                     postmaster::set_spawner($spawner);
-                    postmaster::register(<$address_enum>::$agent_address, MAILBOX.inner.sender().into()).await.inspect(|_| {
+                    postmaster::register(<$address_enum>::$agent_address, &MAILBOX.inner, $overflow).await.inspect(|_| {
 
                         #[task]
                         async fn run_agent(agent: $agent) {
-                            agent.run(MAILBOX.inner.receiver().into()).await
+                            let inbox = post_haste::agent::Inbox::from(&MAILBOX.inner);
+                            agent.run(inbox.with_receive_hook(postmaster::message_received)).await
                         }
                         $spawner.must_spawn(run_agent(agent));
                     })
                 }};
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, watch) => {
+                    crate::postmaster::register_agent!($spawner, $agent_address, $agent, $config, 1, overflow = post_haste::overflow::OverflowPolicy::OverwriteLatest)
+                };
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size: expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!(
+                        @register $spawner, $agent_address, $agent, $config,
+                        post_haste::dependencies::PriorityChannel<post_haste::dependencies::NoopRawMutex, post_haste::priority::Queued<crate::postmaster::Message>, post_haste::dependencies::Max, $queue_size>,
+                        $overflow
+                    )
+                };
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, priority_queue = $queue_size: expr) => {
+                    crate::postmaster::register_agent!($spawner, $agent_address, $agent, $config, priority_queue = $queue_size, overflow = post_haste::overflow::OverflowPolicy::Block)
+                };
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!($spawner, $agent_address, $agent, $config, 1, overflow = $overflow)
+                };
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, $queue_size: expr, overflow = $overflow:expr) => {
+                    crate::postmaster::register_agent!(
+                        @register $spawner, $agent_address, $agent, $config,
+                        post_haste::dependencies::Channel<post_haste::dependencies::NoopRawMutex, crate::postmaster::Message, $queue_size>,
+                        $overflow
+                    )
+                };
+                ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr, $queue_size: expr) => {
+                    crate::postmaster::register_agent!($spawner, $agent_address, $agent, $config, $queue_size, overflow = post_haste::overflow::OverflowPolicy::Block)
+                };
                 ($spawner:ident, $agent_address:ident, $agent:ty, $config:expr) => {
                     crate::postmaster::register_agent!($spawner, $agent_address, $agent, $config, 1)
                 }
@@ -154,6 +333,9 @@ macro_rules! init_postmaster {
             /// When registering an Agent (using the register_agent!() macro), the Agent's message queue is generated and assigned to the given address automatically.
             /// However, there may be some scenarios where you may want to register a message queue without tying it to an Agent.
             /// An example of this would be if you wanted to be able to receive messages on the main task, or providing a debug node for Agents to send diagnostics to.
+            /// The mailbox may either be an ordinary `Channel` (or its sender), or a `PriorityChannel` holding its messages as `post_haste::priority::Queued<postmaster::Message>`.
+            /// The overflow policy determines what happens when a message is sent to the address while its queue is full.
+            /// The `DropOldest` and `OverwriteLatest` policies require the Postmaster to be able to remove messages from the queue, so the mailbox must be given as a reference to a `Channel` (rather than its sender), otherwise registration fails with `OverflowPolicyUnsupported`.
             ///
             /// # Example
             /// ```rust
@@ -162,12 +344,13 @@ macro_rules! init_postmaster {
             /// use embassy_executor::Spawner;
             /// use embassy_sync::channel::Channel;
             ///
+            /// static MESSAGE_QUEUE: Channel<CriticalSectionRawMutex, postmaster::Message, 4> = Channel::new();
+            ///
             /// #[embassy_executor::main]
             /// async fn main(spawner: Spawner) {
-            ///   let message_queue = Channel::new();
-            ///   postmaster::register(Address::MainTask, message_queue.sender().into()).unwrap()
+            ///   postmaster::register(Address::MainTask, &MESSAGE_QUEUE, OverflowPolicy::Block).await.unwrap();
             ///
-            ///   let receiver = message_queue.receiver();
+            ///   let receiver = MESSAGE_QUEUE.receiver();
             ///   loop {
             ///     let received_message = receiver.receive().await;
             ///     // Handle message...
@@ -177,15 +360,19 @@ macro_rules! init_postmaster {
             #[cfg(target_os = "none")]
             pub async fn register(
                 address: $address_enum,
-                mailbox: DynamicSender<'static, Message>,
+                mailbox: impl Into<InboxSender<Message>>,
+                overflow: OverflowPolicy,
             ) -> Result<(), PostmasterError> {
-                postmaster_internal::register(address, mailbox).await
+                postmaster_internal::register(address, mailbox.into(), overflow).await
             }
 
             /// This function can be used to register a standalone address with the Postmaster.
             /// When registering an Agent (using the register_agent!() macro), the Agent's message queue is generated and assigned to the given address automatically.
             /// However, there may be some scenarios where you may want to register a message queue without tying it to an Agent.
             /// An example of this would be if you wanted to be able to receive messages on the main task, or providing a debug node for Agents to send diagnostics to.
+            /// The mailbox may either be an ordinary `tokio::sync::mpsc` queue, or one of the queues created with `post_haste::queue::fifo_channel()` or `post_haste::queue::priority_channel()`.
+            /// The overflow policy determines what happens when a message is sent to the address while its queue is full.
+            /// The `DropOldest` and `OverwriteLatest` policies require the Postmaster to be able to remove messages from the queue, which is only possible with a queue created by `post_haste::queue::fifo_channel()`, otherwise registration fails with `OverflowPolicyUnsupported`.
             ///
             /// # Example
             /// ```rust
@@ -195,8 +382,8 @@ macro_rules! init_postmaster {
             ///
             /// #[tokio::main]
             /// async fn main() {
-            ///   let (sender, receiver) = channel::<postmaster::Message>();
-            ///   postmaster::register(Address::MainTask, sender).unwrap()
+            ///   let (sender, receiver) = channel::<postmaster::Message>(4);
+            ///   postmaster::register(Address::MainTask, sender, OverflowPolicy::Block).await.unwrap();
             ///
             ///   loop {
             ///     let received_message = receiver.recv().await.unwrap();
@@ -207,27 +394,68 @@ macro_rules! init_postmaster {
             #[cfg(not(target_os = "none"))]
             pub async fn register(
                 address: $address_enum,
-                mailbox: Sender<Message>,
+                mailbox: impl Into<InboxSender<Message>>,
+                overflow: OverflowPolicy,
             ) -> Result<(), PostmasterError> {
-                postmaster_internal::register(address, mailbox).await
+                postmaster_internal::register(address, mailbox.into(), overflow).await
+            }
+
+            /// Removes the mailbox registered to the given address, returning its sender.
+            /// Any subsequent messages sent to the address will fail with `NoRecipient`, until a new mailbox is registered to it.
+            /// If the address belongs to an Agent, the Agent will continue to run, but will no longer receive any messages.
+            /// Returns `NoRecipient` if no mailbox is registered to the address.
+            pub async fn unregister(
+                address: $address_enum,
+            ) -> Result<InboxSender<Message>, PostmasterError> {
+                postmaster_internal::unregister(address).await
+            }
+
+            /// Registers a mailbox to the given address with the given overflow policy, replacing any mailbox which was previously registered to it.
+            /// Returns the sender of the previous mailbox, or `None` if the address was not registered.
+            /// Messages already queued in the previous mailbox are not moved to the new one.
+            /// Fails with `OverflowPolicyUnsupported` in the same circumstances as `postmaster::register()`, in which case the previous mailbox is left in place.
+            pub async fn replace(
+                address: $address_enum,
+                mailbox: impl Into<InboxSender<Message>>,
+                overflow: OverflowPolicy,
+            ) -> Result<Option<InboxSender<Message>>, PostmasterError> {
+                postmaster_internal::replace(address, mailbox.into(), overflow).await
             }
 
+            /// Delivers messages sent to `address` to the mailbox registered to `via`, replacing any mailbox which was previously registered to `address`.
+            /// The recipient can tell which address a message was sent to from `Message::destination()`.
+            /// The forwarding lasts until `address` is unregistered or replaced, even if `via` is unregistered first.
+            /// Fails with `NoRecipient` if no mailbox is registered to `via`.
+            /// `post_haste::bridge::Bridge` uses this to receive the messages sent to the addresses which live on another device.
+            pub async fn forward(
+                address: $address_enum,
+                via: $address_enum,
+            ) -> Result<(), PostmasterError> {
+                postmaster_internal::forward(address, via).await
+            }
 
             /// Send a message using the Postmaster's default timeout
             /// The Postmaster will attempt to push the message onto the destination Agent's queue.
             /// The future returned by this function will resolve when either:
             /// - The message was added to the queue successfully
             /// - The message could not be added to the queue before the timeout expired.
+            ///
             /// Reasons for failure include:
             /// - The message queue being consistently full for longer than the timeout
             /// - The Postmaster being unable to acquire a lock on the senders before the timeout expires
             /// - There being no recipient registered at the destination address
+            ///
+            /// If the message could not be delivered, it is handed back in the returned `SendError` (unless it was forwarded to the dead-letter address).
             pub async fn send(
                 destination: $address_enum,
                 source: $address_enum,
                 payload: $payload_enum,
-            ) -> Result<(), PostmasterError> {
-                postmaster_internal::send_internal(destination, Message { source, payload }, None)
+            ) -> Result<(), SendError> {
+                postmaster_internal::send_internal(
+                    destination,
+                    Message::new(source, payload),
+                    None,
+                )
                     .await
             }
 
@@ -237,12 +465,94 @@ macro_rules! init_postmaster {
             /// - The recipient's message queue being full
             /// - The lock on the senders not being available
             /// - There being no recipient registered at the destination address
+            ///
+            /// As with `postmaster::send()`, an undelivered message is handed back in the returned `SendError`.
             pub fn try_send(
                 destination: $address_enum,
                 source: $address_enum,
                 payload: $payload_enum,
+            ) -> Result<(), SendError> {
+                postmaster_internal::try_send_internal(
+                    destination,
+                    Message::new(source, payload),
+                )
+            }
+
+            /// Send a request and await the recipient's reply
+            /// The message is delivered in the same way as `postmaster::send()`, but carries a `ReplyToken` which the recipient uses to respond with `postmaster::reply()`.
+            /// The future returned by this function resolves to the reply message once it arrives.
+            /// Reasons for failure include:
+            /// - Any of the reasons for which `postmaster::send()` can fail
+            /// - No reply being received before the Postmaster's request timeout expires (see `postmaster::set_request_timeout()`)
+            /// - The recipient dropping the `ReplyToken` without replying (tokio only)
+            /// - All reply slots being in use by other pending requests (Embassy only)
+            pub async fn request(
+                destination: $address_enum,
+                source: $address_enum,
+                payload: $payload_enum,
+            ) -> Result<Message, PostmasterError> {
+                postmaster_internal::request_internal(destination, source, payload).await
+            }
+
+            /// Reply to a request made with `postmaster::request()`
+            /// The `ReplyToken` is taken from the received request message, and is consumed so that each request can only be replied to once.
+            /// The reply bypasses the recipient's message queue, so this function never needs to wait.
+            /// This function will fail if the requester is no longer waiting for the reply, e.g. because its request timed out.
+            pub fn reply(
+                token: ReplyToken,
+                source: $address_enum,
+                payload: $payload_enum,
             ) -> Result<(), PostmasterError> {
-                postmaster_internal::try_send_internal(destination, Message { source, payload })
+                postmaster_internal::reply_internal(
+                    token,
+                    Message::new(source, payload),
+                )
+            }
+
+            /// Send a message to every address in a group
+            /// A group is simply a slice of addresses, so named groups can be defined as constants, e.g. `const SENSORS: &[Address] = &[Address::Imu, Address::Barometer];`.
+            /// As the payload is consumed by each recipient, a copy is produced for each of them by calling the provided `payload` closure.
+            /// Each recipient is sent its message in turn, in the same way as `postmaster::send()`, using the Postmaster's default timeout.
+            /// A failure to deliver to one recipient does not prevent delivery to the others: the returned `DeliveryReport` contains the result for each recipient.
+            pub async fn multicast(
+                group: &[$address_enum],
+                source: $address_enum,
+                payload: impl Fn() -> $payload_enum,
+            ) -> DeliveryReport {
+                postmaster_internal::multicast_internal(group, source, payload).await
+            }
+
+            /// Send a message to every registered address
+            /// This function works in exactly the same way as `postmaster::multicast()`, with the group being every address which has a recipient registered at the time of the call.
+            pub async fn broadcast(
+                source: $address_enum,
+                payload: impl Fn() -> $payload_enum,
+            ) -> DeliveryReport {
+                postmaster_internal::broadcast_internal(source, payload).await
+            }
+
+            /// Subscribe an address to a topic
+            /// Any messages subsequently published to the topic with `postmaster::publish()` will be delivered to the address.
+            /// Subscribing an address which is already subscribed has no effect.
+            pub fn subscribe(topic: Topic, subscriber: $address_enum) {
+                postmaster_internal::set_subscription(topic, subscriber, true)
+            }
+
+            /// Unsubscribe an address from a topic
+            /// Unsubscribing an address which is not subscribed has no effect.
+            pub fn unsubscribe(topic: Topic, subscriber: $address_enum) {
+                postmaster_internal::set_subscription(topic, subscriber, false)
+            }
+
+            /// Publish a message to every address subscribed to a topic
+            /// This allows the source to send messages without knowing who will receive them.
+            /// This function works in exactly the same way as `postmaster::multicast()`, with the group being every address subscribed to the topic at the time of the call.
+            pub async fn publish(
+                topic: Topic,
+                source: $address_enum,
+                payload: impl Fn() -> $payload_enum,
+            ) -> DeliveryReport {
+                postmaster_internal::publish_internal(topic, source, payload).await
             }
 
             /// Begin building a message with custom settings
@@ -255,24 +565,117 @@ macro_rules! init_postmaster {
             ) -> MessageBuilder {
                 MessageBuilder {
                     destination,
-                    message: Message { source, payload },
+                    message: Message::new(source, payload),
                     timeout: None,
-                    delay: None,
+                    retry: None,
                 }
             }
 
             /// Retrieve diagnostic information for the Postmaster
             /// The diagnostics contain information about how many messages have been sent in total since boot, and how many (if any) sending failures have occurred.
+            /// These totals are also available for each source and destination address, and the failures for each kind of error.
+            /// On tokio, they also contain how many times supervised Agents have panicked and been restarted.
             /// This provides a simple high-level overview of the health of the system.
             pub fn get_diagnostics() -> Diagnostics {
                 postmaster_internal::get_diagnostics()
             }
 
+            /// Creates the message queue for an Agent registered with `register_agent!()`.
+            /// Policies which evict messages require a queue from which the Postmaster can remove messages, which `tokio::sync::mpsc` does not allow.
+            #[doc(hidden)]
+            #[cfg(not(target_os = "none"))]
+            pub fn agent_mailbox(
+                queue_size: usize,
+                prioritised: bool,
+                overflow: OverflowPolicy,
+            ) -> (InboxSender<Message>, post_haste::agent::Inbox<Message>) {
+                let (sender, inbox): (_, post_haste::agent::Inbox<Message>) = if prioritised {
+                    let (sender, receiver) = priority_channel(queue_size);
+                    (sender.into(), receiver.into())
+                } else if overflow.evicts() {
+                    let (sender, receiver) = fifo_channel(queue_size);
+                    (sender.into(), receiver.into())
+                } else {
+                    let (sender, receiver) = channel(queue_size);
+                    (sender.into(), receiver.into())
+                };
+                (sender, inbox.with_receive_hook(message_received))
+            }
+
+            /// Records that a supervised Agent has panicked, and whether it is being restarted.
+            /// Called by the supervisor generated by `register_agent!()`.
+            #[doc(hidden)]
+            #[cfg(not(target_os = "none"))]
+            pub fn report_agent_panic(restarted: bool) {
+                postmaster_internal::report_agent_panic(restarted)
+            }
+
+            /// Enable or disable the measurement of message latency (disabled by default)
+            /// While enabled, each message is timestamped as it is sent, and the time taken for it to be received from the recipient's inbox is recorded against the recipient's address.
+            /// Replies to requests are not measured, as they bypass the requester's inbox.
+            /// The statistics for each address can be obtained with `postmaster::get_latency_stats()`.
+            pub fn set_latency_tracking(enabled: bool) {
+                postmaster_internal::set_latency_tracking(enabled)
+            }
+
+            /// Retrieve the latency statistics for messages received at the given address since latency tracking was enabled (or since `postmaster::reset_latency_stats()` was last called)
+            pub fn get_latency_stats(address: $address_enum) -> LatencyStats {
+                postmaster_internal::get_latency_stats(address)
+            }
+
+            /// Clear the latency statistics for every address, e.g. to begin a new measurement window
+            pub fn reset_latency_stats() {
+                postmaster_internal::reset_latency_stats()
+            }
+
+            /// Record the latency of a message which has just been received
+            /// This is called automatically for messages received by Agents registered with `register_agent!()`.
+            /// Standalone mailboxes registered with `postmaster::register()` should call it on each message they receive in order for their latency to be measured.
+            /// Messages which were sent while latency tracking was disabled are ignored.
+            pub fn message_received(message: &Message) {
+                postmaster_internal::message_received(message)
+            }
+
             /// Change the Postmaster's default timeout for sending messages
             pub fn set_timeout(timeout_us: u32) {
                 postmaster_internal::set_timeout(timeout_us)
             }
 
+            /// Change how long `postmaster::request()` waits for a reply before giving up (100 ms by default)
+            pub fn set_request_timeout(timeout_us: u32) {
+                postmaster_internal::set_request_timeout(timeout_us)
+            }
+
+            /// Set a handler to be called whenever a delayed message cannot be delivered
+            /// As a delayed message is delivered after `send()` has returned, any failure to deliver it cannot be returned to the sender.
+            /// Instead, the Postmaster calls this handler with the source and destination of the message and the reason it could not be delivered.
+            /// A typical handler will notify the source of the failure, e.g. by calling `postmaster::try_send()` with a failure payload addressed to `failure.source`.
+            /// The handler is called from within the Postmaster's delayed message task, so it should return quickly.
+            pub fn set_delivery_failure_handler(handler: fn(DeliveryFailure)) {
+                postmaster_internal::set_delivery_failure_handler(Some(handler))
+            }
+
+            /// Remove the handler set with `postmaster::set_delivery_failure_handler()`, so that delayed message failures are once again discarded
+            pub fn clear_delivery_failure_handler() {
+                postmaster_internal::set_delivery_failure_handler(None)
+            }
+
+            /// Set the interceptors through which every message passes before it is pushed onto the recipient's queue
+            /// This allows logging, access control, fault injection or tracing to be implemented once, rather than at every call site.
+            /// Each interceptor is a function which is given the message's destination and a mutable reference to the message, and returns a `Verdict` deciding whether the message is passed on, dropped, rejected or rerouted.
+            /// The interceptors are called in the order in which they appear in the slice, each seeing the destination (and any modifications) decided by those before it.
+            /// Replies to requests bypass the recipient's queue, so are not intercepted.
+            /// Interceptors are called from within the sending task, so they should return quickly.
+            /// When no interceptors are set, the only cost to each message is a single atomic load.
+            pub fn set_interceptors(interceptors: &'static [Interceptor]) {
+                postmaster_internal::set_interceptors(interceptors)
+            }
+
+            /// Remove the interceptors set with `postmaster::set_interceptors()`
+            pub fn clear_interceptors() {
+                postmaster_internal::set_interceptors(&[])
+            }
+
             /// Pass a reference to the spawner to the Postmaster for use in delayed messages.
             /// Please note that you should not need to call this function, as the Postmaster automatically acquires a reference to the spawner when an Agent is registered with `register_agent!()`.
             #[cfg(target_os = "none")]
@@ -280,6 +683,71 @@ macro_rules! init_postmaster {
                 postmaster_internal::set_spawner(spawner)
             }
 
+            /// Start recording every message delivered by the Postmaster, along with the time it was sent, its source and its destination (tokio only)
+            /// Payloads are recorded as bytes using the given encoding function, so that the recording can be saved and later replayed with `postmaster::replay()`, e.g. on a desktop rather than the device on which it was made.
+            /// Messages are recorded once they have been pushed onto the recipient's queue, so messages which could not be delivered are not recorded, and nor are replies to requests (which bypass the requester's queue).
+            /// Any recording already in progress is discarded.
+            /// Returns an error if the target is a file which could not be created.
+            #[cfg(not(target_os = "none"))]
+            pub fn start_recording(
+                target: RecordingTarget,
+                encode: fn(&$payload_enum) -> Vec<u8>,
+            ) -> std::io::Result<()> {
+                postmaster_internal::start_recording(target, encode)
+            }
+
+            /// Retrieve a copy of the messages recorded so far by a recording to `RecordingTarget::RingBuffer`, without stopping the recording (tokio only)
+            /// This allows the recent history of messages to be dumped when a fault is detected.
+            /// The trace is empty if nothing is being recorded, or if the recording is being written to a file.
+            #[cfg(not(target_os = "none"))]
+            pub fn recorded_trace() -> Trace {
+                postmaster_internal::recorded_trace()
+            }
+
+            /// Stop the recording started with `postmaster::start_recording()` (tokio only)
+            /// Returns the messages held in the ring buffer, or `None` if the recording was written to a file (which can be read with `Trace::load()`) or nothing was being recorded.
+            /// Returns an error if writing to the file failed at any point during the recording.
+            #[cfg(not(target_os = "none"))]
+            pub fn stop_recording() -> std::io::Result<Option<Trace>> {
+                postmaster_internal::stop_recording()
+            }
+
+            /// Re-send the messages in a recorded trace, with the same sources, destinations and payloads, and at the same times relative to the start of the trace (tokio only)
+            /// Payloads are decoded using the given function, which should reverse the encoding function passed to `postmaster::start_recording()`.
+            /// The trace should usually be replayed into a fresh set of Agents, e.g. within a new `Instance`.
+            /// As those Agents send their own messages in response to the ones replayed, the trace should first be filtered down to the messages which came from outside the Agents being tested, e.g. `trace.records.retain(|record| record.source == Address::Sensor)`.
+            /// Every payload is decoded before any message is sent, failing with `UndecodablePayload` if one cannot be decoded.
+            /// Replay stops at the first message which cannot be delivered, returning the error.
+            #[cfg(not(target_os = "none"))]
+            pub async fn replay(
+                trace: &Trace,
+                decode: fn(&[u8]) -> Option<$payload_enum>,
+            ) -> Result<(), PostmasterError> {
+                let payloads = trace
+                    .records
+                    .iter()
+                    .map(|record| decode(&record.payload).ok_or(PostmasterError::UndecodablePayload))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let started = Instant::now();
+                for (record, payload) in trace.records.iter().zip(payloads) {
+                    time::sleep_until(started + record.elapsed).await;
+                    send(record.destination, record.source, payload).await?;
+                }
+                Ok(())
+            }
+
+            /// Spawn a task which uses the same Postmaster as the current task (tokio only)
+            /// A task spawned with `tokio::spawn()` always uses the default Postmaster, even if it is spawned from within the scope of an `Instance`, so any task which sends messages on behalf of an instance should be spawned with this instead.
+            /// Agents registered with `register_agent!()` are spawned this way automatically.
+            #[cfg(not(target_os = "none"))]
+            pub fn spawn<F>(future: F) -> task::JoinHandle<F::Output>
+            where
+                F: Future + Send + 'static,
+                F::Output: Send + 'static,
+            {
+                postmaster_internal::spawn(future)
+            }
+
             impl MessageBuilder {
                 /// Add a custom timeout to the message.
                 /// When the message is sent, it will use this timeout to determine how long to wait before giving up, rather than the Postmaster's default timeout.
@@ -288,45 +756,199 @@ macro_rules! init_postmaster {
                     self
                 }
 
+                /// Set the priority of the message (`Priority::Normal` by default).
+                /// If the recipient was registered with a priority queue, the message will be received ahead of any lower priority messages waiting in its queue.
+                pub fn with_priority(mut self, priority: Priority) -> Self {
+                    self.message.priority = priority;
+                    self
+                }
+
+                /// Mark the message as having been caused by a message which was received, e.g. when forwarding it or acting on it.
+                /// The message joins the received message's chain, sharing its correlation ID, with a hop count one greater (see `Envelope`).
+                pub fn caused_by(mut self, cause: &Message) -> Self {
+                    self.message.envelope = cause.envelope.next();
+                    self
+                }
+
+                /// Re-attempt delivery of the message if it fails, according to the given policy.
+                /// If an attempt fails with `Timeout`, `TrySendFailed` or `NoRecipient`, the message is handed to the Postmaster's scheduler (as for a delayed message), which makes the next attempt once the policy's backoff has elapsed.
+                /// This is useful during boot, when a message may be sent to an Agent which has not yet been registered.
+                /// Once the message has been handed to the scheduler, `send()` returns successfully, and any final failure to deliver it is passed to the handler set with `postmaster::set_delivery_failure_handler()` (if any) and counted in the `retries_exhausted` diagnostics.
+                /// The retry policy is kept if a delay is added to the message, but not if it is made periodic.
+                pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+                    self.retry.replace(policy);
+                    self
+                }
+
                 /// Add a delay to the message.
                 /// The message is sent immediately, but the Postmaster will not attempt to push the message onto the recipient's queue until the delay has elapsed.
-                /// **Please note** that if a delay is added to the message, but after the delay has elapsed the Postmaster is unable to deliver the message, there is no way for the Postmaster to relay this failure back to the sender.
-                pub fn with_delay(mut self, delay: Duration) -> Self {
-                    self.delay.replace(delay);
-                    self
+                /// This turns the builder into a `DelayedMessageBuilder`, whose `send()` function returns a handle which can be used to cancel or reschedule the message before it is delivered.
+                /// **Please note** that if a delay is added to the message, but after the delay has elapsed the Postmaster is unable to deliver the message, the failure cannot be returned to the sender.
+                /// Instead, it is passed to the handler set with `postmaster::set_delivery_failure_handler()` (if any).
+                pub fn with_delay(self, delay: Duration) -> DelayedMessageBuilder {
+                    DelayedMessageBuilder {
+                        destination: self.destination,
+                        message: self.message,
+                        timeout: self.timeout,
+                        retry: self.retry,
+                        delay,
+                    }
+                }
+
+                /// Make the message periodic.
+                /// Rather than being delivered once, the message will be delivered repeatedly at a fixed interval until it is cancelled, with the first delivery made once the period has elapsed.
+                /// This turns the builder into a `PeriodicMessageBuilder`, whose `send()` function returns a handle which can be used to cancel the message.
+                /// As each delivery needs its own payload, `copy_payload` is called to produce it from the payload given to `postmaster::message()`.
+                /// If the payload type implements `Clone`, `Payloads::clone` can be used here.
+                /// Deliveries are scheduled relative to the first, so the interval does not drift over time.
+                ///
+                /// # Panics
+                /// Panics if the period is zero, as the message would then be due for delivery again as soon as it had been delivered.
+                pub fn with_period(
+                    self,
+                    period: Duration,
+                    copy_payload: fn(&$payload_enum) -> $payload_enum,
+                ) -> PeriodicMessageBuilder {
+                    assert!(period > Duration::from_micros(0), "the period of a periodic message must be non-zero");
+                    PeriodicMessageBuilder {
+                        destination: self.destination,
+                        message: self.message,
+                        timeout: self.timeout,
+                        period,
+                        copy_payload,
+                        repeat_count: None,
+                    }
                 }
 
                 /// Send the configured message.
                 /// This function works in exactly the same way as `postmaster::send()`, except that the timeout scenario may be different depending on whether the timeout for the message was customised.
-                /// If a delay was set, the message will "send" immediately (meaning that the sender can continue executing), but the message won't be delivered until _at least_ the delay has elapsed.
                 /// This function can fail for the following reasons:
                 /// - The message queue being consistently full for longer than the timeout
                 /// - The Postmaster being unable to acquire a lock on the senders before the timeout expires
                 /// - There being no recipient registered at the destination address
-                /// - If a delay was set, the Postmaster was unable to spawn a task to handle sending the message after the delay
-                pub async fn send(self) -> Result<(), PostmasterError> {
-                    match self.delay {
-                        Some(delay) => {
-                            postmaster_internal::spawn_delayed_send(
+                ///
+                /// As with `postmaster::send()`, an undelivered message is handed back in the returned `SendError`.
+                /// If a retry policy was added with `with_retry()`, this function only fails if the first attempt fails with an error which is not retried, if the policy allows no retries, or if the retry cannot be scheduled (e.g. because the delayed message pool is full).
+                pub async fn send(self) -> Result<(), SendError> {
+                    match self.retry {
+                        Some(policy) => {
+                            postmaster_internal::retry_delivery(
                                 self.destination,
                                 self.message,
-                                delay,
                                 self.timeout,
+                                Retry::new(policy),
                             )
                             .await
                         }
                         None => {
-                            postmaster_internal::send_internal(
-                                self.destination,
-                                self.message,
-                                self.timeout,
-                            )
-                            .await
+                            postmaster_internal::send_internal(self.destination, self.message, self.timeout)
+                                .await
                         }
                     }
                 }
             }
 
+            impl DelayedMessageBuilder {
+                /// Add a custom timeout to the message.
+                /// Once the delay has elapsed, the Postmaster will use this timeout to determine how long to wait before giving up, rather than the Postmaster's default timeout.
+                pub fn with_timeout(mut self, timeout: Duration) -> Self {
+                    self.timeout.replace(timeout);
+                    self
+                }
+
+                /// Set the priority of the message (`Priority::Normal` by default).
+                /// If the recipient was registered with a priority queue, the message will be received ahead of any lower priority messages waiting in its queue.
+                pub fn with_priority(mut self, priority: Priority) -> Self {
+                    self.message.priority = priority;
+                    self
+                }
+
+                /// Send the configured message.
+                /// The message will "send" immediately (meaning that the sender can continue executing), but the message won't be delivered until _at least_ the delay has elapsed.
+                /// The returned handle can be used to cancel or reschedule the message before it is delivered.
+                /// Dropping the handle does not cancel the message.
+                /// This function can fail for the following reasons:
+                /// - The delayed message pool being full (Embassy only)
+                /// - The Postmaster being unable to spawn its scheduler task (Embassy only)
+                pub async fn send(self) -> Result<DelayedMessageHandle, PostmasterError> {
+                    postmaster_internal::spawn_delayed_send(
+                        self.destination,
+                        self.message,
+                        self.delay,
+                        self.timeout,
+                        None,
+                        self.retry.map(Retry::new),
+                    )
+                    .await
+                }
+            }
+
+            impl PeriodicMessageBuilder {
+                /// Add a custom timeout to the message.
+                /// Each time the message is delivered, the Postmaster will use this timeout to determine how long to wait before giving up, rather than the Postmaster's default timeout.
+                pub fn with_timeout(mut self, timeout: Duration) -> Self {
+                    self.timeout.replace(timeout);
+                    self
+                }
+
+                /// Set the priority of the message (`Priority::Normal` by default).
+                /// If the recipient was registered with a priority queue, the message will be received ahead of any lower priority messages waiting in its queue.
+                pub fn with_priority(mut self, priority: Priority) -> Self {
+                    self.message.priority = priority;
+                    self
+                }
+
+                /// Limit the number of times the message is delivered.
+                /// Once the message has been delivered `count` times it will stop repeating, as if it had been cancelled.
+                ///
+                /// # Panics
+                /// Panics if the count is zero, as the message is always delivered at least once.
+                pub fn with_repeat_count(mut self, count: u32) -> Self {
+                    assert!(count > 0, "the repeat count of a periodic message must be non-zero");
+                    self.repeat_count.replace(count);
+                    self
+                }
+
+                /// Send the configured message.
+                /// The message will "send" immediately (meaning that the sender can continue executing), and will then be delivered each time the period elapses.
+                /// The returned handle can be used to cancel the message, stopping any further deliveries.
+                /// Rescheduling the message with the handle delays the next delivery, with subsequent deliveries following at the configured period.
+                /// Dropping the handle does not cancel the message.
+                /// If any of the deliveries fail, the failure is passed to the handler set with `postmaster::set_delivery_failure_handler()` (if any), and the message continues to repeat.
+                /// This function can fail for the following reasons:
+                /// - The delayed message pool being full (Embassy only)
+                /// - The Postmaster being unable to spawn its scheduler task (Embassy only)
+                pub async fn send(self) -> Result<DelayedMessageHandle, PostmasterError> {
+                    postmaster_internal::spawn_delayed_send(
+                        self.destination,
+                        self.message,
+                        self.period,
+                        self.timeout,
+                        Some(postmaster_internal::Repetition {
+                            period: self.period,
+                            copy_payload: self.copy_payload,
+                            remaining: self.repeat_count,
+                        }),
+                        None,
+                    )
+                    .await
+                }
+            }
+
+            impl DelayedMessageHandle {
+                /// Cancel the delayed message, so that it is never delivered (or for a periodic message, so that no further deliveries are made).
+                /// This function will fail if the message has already been delivered (or cancelled).
+                pub fn cancel(self) -> Result<(), PostmasterError> {
+                    postmaster_internal::cancel_delayed_send(&self)
+                }
+
+                /// Restart the delay, so that the message will be delivered once the new delay has elapsed (measured from now).
+                /// This function will fail if the message has already been delivered (or cancelled).
+                pub fn reschedule(&self, delay: Duration) -> Result<(), PostmasterError> {
+                    postmaster_internal::reschedule_delayed_send(self, delay)
+                }
+            }
+
             /// The structure of a message in the system.
             /// This structure is automatically generated by the sending functions from the source address and the payload
             pub struct Message {
@@ -334,6 +956,139 @@ macro_rules! init_postmaster {
                 pub source: $address_enum,
                 /// The message contents
                 pub payload: $payload_enum,
+                /// Present if the message was sent with `postmaster::request()`, in which case the sender is awaiting a reply.
+                /// Pass it to `postmaster::reply()` to respond.
+                pub reply_token: Option<ReplyToken>,
+                /// The priority of the message, which determines the order in which it is received by Agents with a priority queue.
+                /// Set using `MessageBuilder::with_priority()`, otherwise `Priority::Normal`.
+                pub priority: Priority,
+                /// Metadata stamped onto the message by the Postmaster as it is sent.
+                pub envelope: Envelope,
+                /// Present if the message could not be delivered, and has instead been forwarded to the dead-letter address given to `init_postmaster!()`.
+                pub dead_letter: Option<DeadLetter>,
+                /// The index of the address to which the message was sent (after any rerouting by interceptors), set as it is sent.
+                destination: Option<usize>,
+                /// Whether the latency of the message is measured as it is received, which is set while latency tracking is enabled.
+                track_latency: bool,
+            }
+
+            impl Message {
+                fn new(source: $address_enum, payload: $payload_enum) -> Self {
+                    Self {
+                        source,
+                        payload,
+                        reply_token: None,
+                        priority: Priority::Normal,
+                        envelope: Envelope {
+                            sequence: 0,
+                            sent_at: Instant::now(),
+                            correlation_id: 0,
+                            hops: 0,
+                        },
+                        dead_letter: None,
+                        destination: None,
+                        track_latency: false,
+                    }
+                }
+
+                /// The address to which the message was sent, after any rerouting by interceptors.
+                /// For a message sent to a forwarded address (see `postmaster::forward()`), this is the forwarded address rather than the address of the mailbox which received it.
+                /// Returns `None` for a message which has not been sent, e.g. one which has just been deserialized.
+                pub fn destination(&self) -> Option<$address_enum> {
+                    self.destination
+                        .map(|index| <$address_enum as VariantIndex>::from_variant_index(index).expect("address index out of range"))
+                }
+            }
+
+            $crate::_serde_message!($address_enum, $payload_enum);
+            $crate::_bridge_message!($address_enum);
+
+            #[cfg(not(target_os = "none"))]
+            impl post_haste::testing::TestMessage<$address_enum> for Message {
+                fn source(&self) -> $address_enum {
+                    self.source
+                }
+
+                fn sequence(&self) -> u32 {
+                    self.envelope.sequence
+                }
+
+                async fn register(
+                    address: $address_enum,
+                    mailbox: InboxSender<Message>,
+                ) -> Result<(), PostmasterError> {
+                    register(address, mailbox, OverflowPolicy::Block).await
+                }
+
+                async fn unregister(address: $address_enum) -> Result<(), PostmasterError> {
+                    unregister(address).await.map(drop)
+                }
+            }
+
+            /// Details of why a message was forwarded to the dead-letter address.
+            #[derive(Clone, Copy)]
+            pub struct DeadLetter {
+                /// The address to which the message could not be delivered
+                pub destination: $address_enum,
+                /// The reason the message could not be delivered
+                pub error: PostmasterError,
+            }
+
+            /// Metadata stamped onto each message by the Postmaster as it is sent.
+            /// This allows messages to be correlated and traced through the system without adding the information to the payloads.
+            #[derive(Clone, Copy, Debug)]
+            pub struct Envelope {
+                /// A number which increases by one for each message sent through the Postmaster (wrapping around after `u32::MAX`).
+                /// If an Agent receives messages from the same source out of sequence, they have been reordered, e.g. by a priority queue.
+                pub sequence: u32,
+                /// The time at which the message was sent.
+                /// For a delayed message, this is the time at which its delay elapsed and the Postmaster began delivering it.
+                pub sent_at: Instant,
+                /// Identifies the chain of messages to which this message belongs.
+                /// A reply to a request, or a message sent using `MessageBuilder::caused_by()`, has the same correlation ID as the message which caused it.
+                /// Any other message starts a new chain, and has its own sequence number as its correlation ID.
+                pub correlation_id: u32,
+                /// The number of messages before this one in its chain, which is 0 for a message which starts a new chain.
+                /// An unexpectedly high hop count suggests that Agents are triggering each other in a loop.
+                pub hops: u16,
+            }
+
+            impl Envelope {
+                /// The envelope for a message caused by the message in this envelope, before it is stamped.
+                fn next(&self) -> Self {
+                    Self {
+                        hops: self.hops.saturating_add(1),
+                        ..*self
+                    }
+                }
+            }
+
+            /// Priority queues receive messages by priority, and then in the order in which they were sent.
+            impl post_haste::priority::Prioritised for Message {
+                fn priority(&self) -> Priority {
+                    self.priority
+                }
+
+                fn sequence(&self) -> u32 {
+                    self.envelope.sequence
+                }
+            }
+
+            /// A handle which allows the recipient of a request to send its reply back to the requester.
+            /// It is attached to the request message by `postmaster::request()` and consumed by `postmaster::reply()`.
+            #[cfg(not(target_os = "none"))]
+            pub struct ReplyToken {
+                sender: oneshot::Sender<Message>,
+                request: Envelope,
+            }
+
+            /// A handle which allows the recipient of a request to send its reply back to the requester.
+            /// It is attached to the request message by `postmaster::request()` and consumed by `postmaster::reply()`.
+            #[cfg(target_os = "none")]
+            pub struct ReplyToken {
+                slot: usize,
+                generation: u32,
+                request: Envelope,
             }
 
             /// A builder for configuring messages.
@@ -342,229 +1097,1859 @@ macro_rules! init_postmaster {
                 destination: $address_enum,
                 message: Message,
                 timeout: Option<Duration>,
-                delay: Option<Duration>,
+                retry: Option<RetryPolicy>,
             }
 
-            /// Contains diagnostic information for the Postmaster.
-            /// Obtained by calling postmaster::get_diagnostics()
-            pub struct Diagnostics {
-                /// The number of messages successfully sent since the Postmaster was initialised.
-                pub messages_sent: usize,
-                /// The number of messages which could not be sent since the Postmaster was initialised.
-                pub send_failures: usize,
+            /// A builder for configuring messages which are to be delivered after a delay.
+            /// Obtained by calling `with_delay()` on a `MessageBuilder`.
+            pub struct DelayedMessageBuilder {
+                destination: $address_enum,
+                message: Message,
+                timeout: Option<Duration>,
+                retry: Option<RetryPolicy>,
+                delay: Duration,
             }
 
-            mod postmaster_internal {
-                use super::{
-                    ADDRESS_COUNT, Message, PostmasterError, $address_enum, $payload_enum,
-                };
-                use core::cell::RefCell;
-                use core::sync::atomic::Ordering;
-                use post_haste::dependencies::*;
-                #[post_haste::dependencies::env_item]
-                const DELAYED_MESSAGE_POOL_SIZE: usize = 8;
+            /// A builder for configuring messages which are to be delivered repeatedly at a fixed interval.
+            /// Obtained by calling `with_period()` on a `MessageBuilder`.
+            pub struct PeriodicMessageBuilder {
+                destination: $address_enum,
+                message: Message,
+                timeout: Option<Duration>,
+                period: Duration,
+                copy_payload: fn(&$payload_enum) -> $payload_enum,
+                repeat_count: Option<u32>,
+            }
 
-                #[cfg(target_os = "none")]
-                type Mailbox = DynamicSender<'static, Message>;
-                #[cfg(not(target_os = "none"))]
-                type Mailbox = Sender<Message>;
+            /// A handle to a delayed or periodic message which has been sent but not yet (fully) delivered.
+            /// Returned by `DelayedMessageBuilder::send()` and `PeriodicMessageBuilder::send()`.
+            /// On tokio, the handle belongs to the Postmaster instance through which the message was sent, so must be used from within the same instance's scope.
+            pub struct DelayedMessageHandle {
+                slot: usize,
+                generation: u32,
+            }
 
-                pub(super) async fn register(
+            /// An independent Postmaster, with its own mailboxes, subscriptions, settings, scheduler and diagnostics (tokio only).
+            /// The functions in `postmaster` act on the default instance, unless they are called from within the scope of another instance (see `Instance::scope()`), in which case they act on that instance instead.
+            /// This allows several copies of a system to run side by side in one process, e.g. tests which run in parallel, separate subsystems, or a simulation of several devices.
+            /// Cloning an `Instance` gives another handle to the same Postmaster.
+            /// The Postmaster lives for as long as any handle to it, or any task spawned within its scope (including its Agents), remains.
+            /// Its scheduler does not keep it alive, and is stopped once the Postmaster has been dropped.
+            #[cfg(not(target_os = "none"))]
+            #[derive(Clone)]
+            pub struct Instance {
+                postmaster: Arc<postmaster_internal::Postmaster>,
+            }
+
+            #[cfg(not(target_os = "none"))]
+            impl Instance {
+                /// Creates a new Postmaster, with no mailboxes registered and the default settings.
+                pub fn new() -> Self {
+                    Self {
+                        postmaster: Arc::new(postmaster_internal::Postmaster::new()),
+                    }
+                }
+
+                /// Runs a future within the scope of this instance.
+                /// Every Postmaster function called by the future (including `register_agent!()`) acts on this instance, as do the Agents it registers and any tasks it spawns with `postmaster::spawn()`.
+                pub fn scope<F: Future>(&self, future: F) -> impl Future<Output = F::Output> + use<F> {
+                    postmaster_internal::scope(Arc::clone(&self.postmaster), future)
+                }
+
+                /// Runs a function within the scope of this instance, e.g. to call `postmaster::try_send()` or `postmaster::get_diagnostics()` from synchronous code.
+                pub fn sync_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+                    postmaster_internal::sync_scope(Arc::clone(&self.postmaster), f)
+                }
+            }
+
+            #[cfg(not(target_os = "none"))]
+            impl Default for Instance {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            /// A function which inspects each message before it is pushed onto the recipient's queue (see `postmaster::set_interceptors()`).
+            /// It is given the message's destination and may modify the message, returning what should happen to it.
+            pub type Interceptor = fn(destination: $address_enum, message: &mut Message) -> Verdict;
+
+            /// The decision made by an `Interceptor` about a message.
+            pub enum Verdict {
+                /// Pass the message (with any modifications) on to the next interceptor, or to its destination.
+                Pass,
+                /// Silently discard the message, as if it had been delivered.
+                /// The message is counted in the destination's `dropped_messages()` diagnostics.
+                Drop,
+                /// Discard the message, and fail the send with a `Rejected` error.
+                Reject,
+                /// Deliver the message to a different address instead.
+                Reroute($address_enum),
+            }
+
+            /// Details of a delayed message which could not be delivered.
+            /// Passed to the handler set with `postmaster::set_delivery_failure_handler()`.
+            pub struct DeliveryFailure {
+                /// The address from which the message originated
+                pub source: $address_enum,
+                /// The address to which the message could not be delivered
+                pub destination: $address_enum,
+                /// The reason the message could not be delivered
+                pub error: PostmasterError,
+            }
+
+            /// The outcome of a call to `postmaster::multicast()` or `postmaster::broadcast()`, holding the result of delivery to each recipient.
+            pub struct DeliveryReport {
+                results: [Option<Result<(), PostmasterError>>; ADDRESS_COUNT],
+            }
+
+            impl DeliveryReport {
+                /// The result of delivering the message to the given address, or `None` if the address was not one of the recipients.
+                pub fn result(&self, address: $address_enum) -> Option<&Result<(), PostmasterError>> {
+                    self.results[address.variant_index()].as_ref()
+                }
+
+                /// The number of recipients to which the message was successfully delivered.
+                pub fn delivered(&self) -> usize {
+                    self.results.iter().filter(|result| matches!(result, Some(Ok(_)))).count()
+                }
+
+                /// The number of recipients to which the message could not be delivered.
+                pub fn failed(&self) -> usize {
+                    self.results.iter().filter(|result| matches!(result, Some(Err(_)))).count()
+                }
+
+                /// Returns true if the message was delivered to every recipient.
+                pub fn is_complete(&self) -> bool {
+                    self.failed() == 0
+                }
+            }
+
+            /// Contains diagnostic information for the Postmaster.
+            /// Obtained by calling postmaster::get_diagnostics()
+            pub struct Diagnostics {
+                /// The number of messages successfully sent since the Postmaster was initialised.
+                pub messages_sent: usize,
+                /// The number of messages which could not be sent since the Postmaster was initialised.
+                pub send_failures: usize,
+                /// The number of times a supervised Agent has panicked since the Postmaster was initialised.
+                #[cfg(not(target_os = "none"))]
+                pub agent_panics: usize,
+                /// The number of times a supervised Agent has been restarted after panicking since the Postmaster was initialised.
+                #[cfg(not(target_os = "none"))]
+                pub agent_restarts: usize,
+                /// The number of undeliverable messages which have been forwarded to the dead-letter address since the Postmaster was initialised.
+                pub dead_letters: usize,
+                /// The number of times delivery of a message has been re-attempted under its `RetryPolicy` since the Postmaster was initialised.
+                pub retries: usize,
+                /// The number of messages sent with a `RetryPolicy` which the Postmaster has given up on since it was initialised, either because every attempt failed or because an attempt failed with an error which is not retried.
+                pub retries_exhausted: usize,
+                messages_sent_to: [usize; ADDRESS_COUNT],
+                messages_sent_from: [usize; ADDRESS_COUNT],
+                send_failures_to: [usize; ADDRESS_COUNT],
+                send_failures_from: [usize; ADDRESS_COUNT],
+                send_failures_with: [usize; ERROR_COUNT],
+                dropped_messages: [usize; ADDRESS_COUNT],
+                queue_high_water_marks: [usize; ADDRESS_COUNT],
+            }
+
+            impl Diagnostics {
+                /// Returns the number of messages successfully sent to the given address since the Postmaster was initialised.
+                pub fn messages_sent_to(&self, address: $address_enum) -> usize {
+                    self.messages_sent_to[address.variant_index()]
+                }
+
+                /// Returns the number of messages successfully sent from the given address since the Postmaster was initialised.
+                /// Unlike `messages_sent_to()`, this includes replies to requests.
+                pub fn messages_sent_from(&self, address: $address_enum) -> usize {
+                    self.messages_sent_from[address.variant_index()]
+                }
+
+                /// Returns the number of messages which could not be sent to the given address since the Postmaster was initialised.
+                pub fn send_failures_to(&self, address: $address_enum) -> usize {
+                    self.send_failures_to[address.variant_index()]
+                }
+
+                /// Returns the number of messages from the given address which could not be sent since the Postmaster was initialised.
+                /// Unlike `send_failures_to()`, this includes replies to requests.
+                pub fn send_failures_from(&self, address: $address_enum) -> usize {
+                    self.send_failures_from[address.variant_index()]
+                }
+
+                /// Returns the number of messages which could not be sent due to the given error since the Postmaster was initialised.
+                pub fn send_failures_with(&self, error: PostmasterError) -> usize {
+                    self.send_failures_with[error.variant_index()]
+                }
+
+                /// Returns the number of messages addressed to the given address which have been discarded by its overflow policy (or dropped by an interceptor) since the Postmaster was initialised.
+                pub fn dropped_messages(&self, address: $address_enum) -> usize {
+                    self.dropped_messages[address.variant_index()]
+                }
+
+                /// Returns the greatest number of messages which have been waiting in the given address's queue at once since the Postmaster was initialised.
+                /// This is measured each time a message is added to the queue.
+                /// On Embassy, it is only measured for mailboxes registered using a reference to their `Channel` or `PriorityChannel` (as `register_agent!()` does), and is otherwise 0.
+                pub fn queue_high_water_mark(&self, address: $address_enum) -> usize {
+                    self.queue_high_water_marks[address.variant_index()]
+                }
+            }
+
+            mod postmaster_internal {
+                use super::{
+                    ADDRESS_COUNT, DEAD_LETTER_ADDRESS, DeadLetter, ERROR_COUNT, Interceptor, LatencyStats, Message, OverflowPolicy, PostmasterError, ReplyToken, SendError, TOPIC_COUNT, Topic, Verdict,
+                    $address_enum, $payload_enum,
+                };
+                use core::cell::RefCell;
+                use core::sync::atomic::Ordering;
+                use post_haste::dependencies::*;
+                #[post_haste::dependencies::env_item]
+                const DELAYED_MESSAGE_POOL_SIZE: usize = 8;
+                #[cfg(target_os = "none")]
+                #[post_haste::dependencies::env_item]
+                const REPLY_SLOT_POOL_SIZE: usize = 4;
+                const DEFAULT_REQUEST_TIMEOUT_US: u32 = 100_000;
+
+                type Mailbox = InboxSender<Message>;
+                type Senders = [Option<Registration>; ADDRESS_COUNT];
+                /// A message which could not be delivered, along with the reason why.
+                type Undelivered = (PostmasterError, Message);
+
+                /// A mailbox registered with the Postmaster, along with how it handles messages which arrive while its queue is full.
+                #[derive(Clone)]
+                struct Registration {
+                    mailbox: Mailbox,
+                    overflow: OverflowPolicy,
+                }
+
+                impl Registration {
+                    fn new(mailbox: Mailbox, overflow: OverflowPolicy) -> Result<Self, PostmasterError> {
+                        if overflow.evicts() && mailbox.evictor().is_none() {
+                            return Err(PostmasterError::OverflowPolicyUnsupported);
+                        }
+                        Ok(Self { mailbox, overflow })
+                    }
+                }
+
+                pub(super) async fn register(
                     address: $address_enum,
                     mailbox: Mailbox,
+                    overflow: OverflowPolicy,
                 ) -> Result<(), PostmasterError> {
-                    let mut senders = POSTMASTER.senders.lock().await;
-                    if senders[address as usize].is_none() {
-                        senders[address as usize].replace(mailbox);
+                    let postmaster = postmaster();
+                    let registration = Registration::new(mailbox, overflow)?;
+                    let mut senders = postmaster.senders.lock().await;
+                    if senders[address.variant_index()].is_none() {
+                        senders[address.variant_index()].replace(registration);
                         Ok(())
                     } else {
                         return Err(PostmasterError::AddressAlreadyTaken);
                     }
                 }
 
+                pub(super) async fn unregister(
+                    address: $address_enum,
+                ) -> Result<Mailbox, PostmasterError> {
+                    postmaster().senders.lock().await[address.variant_index()]
+                        .take()
+                        .map(|registration| registration.mailbox)
+                        .ok_or(PostmasterError::NoRecipient)
+                }
+
+                pub(super) async fn replace(
+                    address: $address_enum,
+                    mailbox: Mailbox,
+                    overflow: OverflowPolicy,
+                ) -> Result<Option<Mailbox>, PostmasterError> {
+                    let registration = Registration::new(mailbox, overflow)?;
+                    Ok(postmaster().senders.lock().await[address.variant_index()]
+                        .replace(registration)
+                        .map(|registration| registration.mailbox))
+                }
+
+                pub(super) async fn forward(
+                    address: $address_enum,
+                    via: $address_enum,
+                ) -> Result<(), PostmasterError> {
+                    let postmaster = postmaster();
+                    let mut senders = postmaster.senders.lock().await;
+                    let registration = senders[via.variant_index()]
+                        .clone()
+                        .ok_or(PostmasterError::NoRecipient)?;
+                    senders[address.variant_index()].replace(registration);
+                    Ok(())
+                }
+
                 pub(super) async fn send_internal(
                     destination: $address_enum,
                     message: Message,
                     timeout: Option<Duration>,
-                ) -> Result<(), PostmasterError> {
+                ) -> Result<(), SendError> {
+                    deliver(destination.variant_index(), message, timeout, true).await
+                }
+
+                pub(super) async fn multicast_internal(
+                    group: &[$address_enum],
+                    source: $address_enum,
+                    payload: impl Fn() -> $payload_enum,
+                ) -> super::DeliveryReport {
+                    let mut targets = [false; ADDRESS_COUNT];
+                    for address in group {
+                        targets[address.variant_index()] = true;
+                    }
+                    deliver_to_targets(targets, source, payload).await
+                }
+
+                pub(super) async fn broadcast_internal(
+                    source: $address_enum,
+                    payload: impl Fn() -> $payload_enum,
+                ) -> super::DeliveryReport {
+                    let postmaster = postmaster();
+                    let targets = {
+                        let senders = postmaster.senders.lock().await;
+                        core::array::from_fn(|index| senders[index].is_some())
+                    };
+                    deliver_to_targets(targets, source, payload).await
+                }
+
+                pub(super) async fn publish_internal(
+                    topic: Topic,
+                    source: $address_enum,
+                    payload: impl Fn() -> $payload_enum,
+                ) -> super::DeliveryReport {
+                    let postmaster = postmaster();
+                    let subscribers = &postmaster.subscribers[topic.variant_index()];
+                    let targets = core::array::from_fn(|index| {
+                        subscribers[index].load(Ordering::Relaxed)
+                    });
+                    deliver_to_targets(targets, source, payload).await
+                }
+
+                pub(super) fn set_subscription(
+                    topic: Topic,
+                    subscriber: $address_enum,
+                    subscribed: bool,
+                ) {
+                    postmaster().subscribers[topic.variant_index()][subscriber.variant_index()]
+                        .store(subscribed, Ordering::Relaxed)
+                }
+
+                async fn deliver_to_targets(
+                    targets: [bool; ADDRESS_COUNT],
+                    source: $address_enum,
+                    payload: impl Fn() -> $payload_enum,
+                ) -> super::DeliveryReport {
+                    let mut report = super::DeliveryReport {
+                        results: [const { None }; ADDRESS_COUNT],
+                    };
+                    for (index, targeted) in targets.into_iter().enumerate() {
+                        if !targeted {
+                            continue;
+                        }
+                        let message = Message::new(source, payload());
+                        report.results[index] = Some(deliver(index, message, None, true).await.map_err(PostmasterError::from));
+                    }
+                    report
+                }
+
+                /// Deliver a message to the address with the given index, waiting until the timeout for space in its queue.
+                /// If `dead_letter` is true, a message which cannot be delivered is forwarded to the dead-letter address (if any) rather than being handed back.
+                async fn deliver(
+                    index: usize,
+                    mut message: Message,
+                    timeout: Option<Duration>,
+                    dead_letter: bool,
+                ) -> Result<(), SendError> {
+                    let postmaster = postmaster();
+                    stamp(&mut message, Some(index));
+                    let source = message.source.variant_index();
+                    let index = match intercept(&postmaster, index, &mut message) {
+                        Ok(Some(index)) => index,
+                        Ok(None) => return Ok(()),
+                        Err(error) => return settle(index, source, Err((error, message)), None),
+                    };
+                    #[cfg(not(target_os = "none"))]
+                    let record = capture(&postmaster, index, &message);
                     let timeout = match timeout {
                         Some(duration) => duration,
                         None => Duration::from_micros(
-                            POSTMASTER.timeout_us.load(Ordering::Relaxed).into(),
+                            postmaster.timeout_us.load(Ordering::Relaxed).into(),
                         ),
                     };
+                    let deadline = Instant::now() + timeout;
                     #[cfg(not(target_os = "none"))]
-                    evaluate_diagnostics(tokio::time::timeout(timeout, async {
-                        match &POSTMASTER.senders.lock().await[destination as usize] {
-                            None => Err(PostmasterError::NoRecipient),
-                            Some(sender) => {
-                                sender.send(message).await?;
-                                Ok(())
-                            }
+                    let senders = time::timeout_at(deadline, postmaster.senders.lock()).await;
+                    #[cfg(target_os = "none")]
+                    let senders = postmaster.senders.lock().with_deadline(deadline).await;
+                    let Ok(senders) = senders else {
+                        return settle(index, source, Err((PostmasterError::Timeout, message)), None);
+                    };
+                    let result = match &senders[index] {
+                        None => Err((PostmasterError::NoRecipient, message)),
+                        Some(registration) if registration.overflow != OverflowPolicy::Block => {
+                            push_with_overflow(index, registration, message)
                         }
+                        Some(registration) => {
+                            let remaining = deadline.saturating_duration_since(Instant::now());
+                            #[cfg(not(target_os = "none"))]
+                            let result = registration.mailbox.send_timeout(message, remaining).await.map_err(|error| match error {
+                                SendTimeoutError::Timeout(message) => (PostmasterError::Timeout, message),
+                                SendTimeoutError::Closed(message) => (PostmasterError::ReceiverClosed, message),
+                            });
+                            #[cfg(target_os = "none")]
+                            let result = registration
+                                .mailbox
+                                .send_timeout(message, remaining)
+                                .await
+                                .map_err(|message| (PostmasterError::Timeout, message));
+                            result.inspect(|_| record_queue_depth(index, &registration.mailbox))
+                        }
+                    };
+                    #[cfg(not(target_os = "none"))]
+                    commit(record, &result);
+                    settle(index, source, result, Some(&*senders).filter(|_| dead_letter))
+                }
+
+                /// Records the outcome of an attempt to deliver a message, forwarding the message to the dead-letter address if it could not be delivered.
+                /// The dead-letter address's mailbox is taken from `senders`, so a message can only be forwarded if the Postmaster was able to lock its table of mailboxes.
+                /// Any message which is not forwarded is handed back in the returned `SendError`.
+                fn settle(
+                    index: usize,
+                    source: usize,
+                    result: Result<(), Undelivered>,
+                    senders: Option<&Senders>,
+                ) -> Result<(), SendError> {
+                    record_outcome(Some(index), source, result.as_ref().err().map(|(error, _)| *error));
+                    result.map_err(|(error, message)| SendError {
+                        error,
+                        destination: <$address_enum as VariantIndex>::from_variant_index(index)
+                            .expect("address index out of range"),
+                        message: match senders {
+                            Some(senders) => forward_dead_letter(senders, index, error, message),
+                            None => Some(message),
+                        },
                     })
-                    .await
-                    .map_err(|_| PostmasterError::Timeout)?)?;
+                }
+
+                /// Forwards a message which could not be delivered to the dead-letter address, if one was given to `init_postmaster!()`.
+                /// The message is pushed onto the dead-letter mailbox without waiting.
+                /// If it cannot be forwarded, the message is returned unchanged.
+                fn forward_dead_letter(
+                    senders: &Senders,
+                    index: usize,
+                    error: PostmasterError,
+                    mut message: Message,
+                ) -> Option<Message> {
+                    let Some(dead_letter_index) = DEAD_LETTER_ADDRESS.map(|address| address.variant_index()) else {
+                        return Some(message);
+                    };
+                    // If the dead-letter mailbox is itself the one which could not be reached, there is nowhere to forward the message
+                    if dead_letter_index == index {
+                        return Some(message);
+                    }
+                    let Some(registration) = &senders[dead_letter_index] else {
+                        return Some(message);
+                    };
+                    message.dead_letter = Some(DeadLetter {
+                        destination: <$address_enum as VariantIndex>::from_variant_index(index)
+                            .expect("address index out of range"),
+                        error,
+                    });
+                    let track_latency = core::mem::take(&mut message.track_latency);
+                    match push_with_overflow(dead_letter_index, registration, message) {
+                        Ok(()) => {
+                            postmaster().counters.dead_letters.fetch_add(1, Ordering::Relaxed);
+                            None
+                        }
+                        Err((_, mut message)) => {
+                            message.dead_letter = None;
+                            message.track_latency = track_latency;
+                            Some(message)
+                        }
+                    }
+                }
+
+                /// Fills in the message's envelope as it is sent to the given destination (or as a reply, if there is no destination).
+                fn stamp(message: &mut Message, destination: Option<usize>) {
+                    let postmaster = postmaster();
+                    let envelope = &mut message.envelope;
+                    envelope.sequence = postmaster.sequence.fetch_add(1, Ordering::Relaxed);
+                    envelope.sent_at = Instant::now();
+                    if envelope.hops == 0 {
+                        envelope.correlation_id = envelope.sequence;
+                    }
+                    if let Some(token) = message.reply_token.as_mut() {
+                        token.request = *envelope;
+                    }
+                    message.destination = destination;
+                    message.track_latency =
+                        destination.is_some() && postmaster.latency_tracking.load(Ordering::Relaxed);
+                }
+
+                pub(super) fn try_send_internal(
+                    destination: $address_enum,
+                    mut message: Message,
+                ) -> Result<(), SendError> {
+                    let postmaster = postmaster();
+                    let index = destination.variant_index();
+                    stamp(&mut message, Some(index));
+                    let source = message.source.variant_index();
+                    let index = match intercept(&postmaster, index, &mut message) {
+                        Ok(Some(index)) => index,
+                        Ok(None) => return Ok(()),
+                        Err(error) => return settle(index, source, Err((error, message)), None),
+                    };
+                    #[cfg(not(target_os = "none"))]
+                    let record = capture(&postmaster, index, &message);
+                    let Ok(senders) = postmaster.senders.try_lock() else {
+                        return settle(index, source, Err((PostmasterError::TryLockFailed, message)), None);
+                    };
+                    let result = match &senders[index] {
+                        None => Err((PostmasterError::NoRecipient, message)),
+                        Some(registration) if registration.overflow != OverflowPolicy::Block => {
+                            push_with_overflow(index, registration, message)
+                        }
+                        Some(registration) => registration
+                            .mailbox
+                            .try_send(message)
+                            .map_err(|error| (PostmasterError::TrySendFailed, unsent_message(error)))
+                            .inspect(|_| record_queue_depth(index, &registration.mailbox)),
+                    };
+                    #[cfg(not(target_os = "none"))]
+                    commit(record, &result);
+                    settle(index, source, result, Some(&senders))
+                }
+
+                fn unsent_message(error: TrySendError<Message>) -> Message {
+                    match error {
+                        TrySendError::Full(message) => message,
+                        #[cfg(not(target_os = "none"))]
+                        TrySendError::Closed(message) => message,
+                    }
+                }
+
+                /// Captures a message for the recording started with `postmaster::start_recording()`, if any, as it is sent to the address with the given index.
+                #[cfg(not(target_os = "none"))]
+                #[inline]
+                fn capture(
+                    postmaster: &Postmaster,
+                    index: usize,
+                    message: &Message,
+                ) -> Option<Record<$address_enum>> {
+                    if !postmaster.recording.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                    let destination = <$address_enum as VariantIndex>::from_variant_index(index)
+                        .expect("address index out of range");
+                    let recorder = postmaster.recorder.lock().unwrap();
+                    recorder
+                        .as_ref()
+                        .map(|recorder| recorder.capture(message.source, destination, &message.payload))
+                }
+
+                /// Keeps a captured message in the recording, provided it was delivered.
+                #[cfg(not(target_os = "none"))]
+                fn commit(record: Option<Record<$address_enum>>, result: &Result<(), Undelivered>) {
+                    let Some(record) = record.filter(|_| result.is_ok()) else {
+                        return;
+                    };
+                    if let Some(recorder) = postmaster().recorder.lock().unwrap().as_mut() {
+                        recorder.push(record);
+                    }
+                }
+
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn start_recording(
+                    target: super::RecordingTarget,
+                    encode: fn(&$payload_enum) -> Vec<u8>,
+                ) -> std::io::Result<()> {
+                    let recorder = Recorder::new(target, encode)?;
+                    let postmaster = postmaster();
+                    postmaster.recorder.lock().unwrap().replace(recorder);
+                    postmaster.recording.store(true, Ordering::Relaxed);
+                    Ok(())
+                }
+
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn recorded_trace() -> super::Trace {
+                    postmaster()
+                        .recorder
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .map(|recorder| recorder.trace())
+                        .unwrap_or_default()
+                }
 
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn stop_recording() -> std::io::Result<Option<super::Trace>> {
+                    let postmaster = postmaster();
+                    postmaster.recording.store(false, Ordering::Relaxed);
+                    let recorder = postmaster.recorder.lock().unwrap().take();
+                    recorder.map_or(Ok(None), |recorder| recorder.finish())
+                }
+
+                /// Passes a message through the interceptors set with `postmaster::set_interceptors()`, in order.
+                /// Returns the index of the address to which the message should be delivered, or `None` if an interceptor dropped it.
+                /// The caller's handle to the Postmaster is passed in, so that when no interceptors are set this costs only a single atomic load.
+                #[inline]
+                fn intercept(
+                    postmaster: &Postmaster,
+                    mut index: usize,
+                    message: &mut Message,
+                ) -> Result<Option<usize>, PostmasterError> {
+                    if !postmaster.intercepting.load(Ordering::Relaxed) {
+                        return Ok(Some(index));
+                    }
+                    #[cfg(not(target_os = "none"))]
+                    let interceptors = *postmaster.interceptors.read().unwrap();
                     #[cfg(target_os = "none")]
-                    evaluate_diagnostics(
-                        async {
-                            match &POSTMASTER.senders.lock().await[destination as usize] {
-                                None => Err(PostmasterError::NoRecipient),
-                                Some(sender) => {
-                                    sender.send(message).await;
-                                    Ok(())
-                                }
+                    let interceptors = *postmaster.interceptors.borrow();
+                    for interceptor in interceptors {
+                        let destination = <$address_enum as VariantIndex>::from_variant_index(index)
+                            .expect("address index out of range");
+                        match interceptor(destination, message) {
+                            Verdict::Pass => {}
+                            Verdict::Drop => {
+                                postmaster.counters.dropped_messages[index].fetch_add(1, Ordering::Relaxed);
+                                return Ok(None);
                             }
+                            Verdict::Reject => return Err(PostmasterError::Rejected),
+                            Verdict::Reroute(destination) => index = destination.variant_index(),
                         }
-                        .with_timeout(timeout)
-                        .await?,
-                    )?;
+                    }
+                    message.destination = Some(index);
+                    Ok(Some(index))
+                }
+
+                pub(super) fn set_interceptors(interceptors: &'static [Interceptor]) {
+                    let postmaster = postmaster();
+                    #[cfg(not(target_os = "none"))]
+                    {
+                        *postmaster.interceptors.write().unwrap() = interceptors;
+                    }
+                    #[cfg(target_os = "none")]
+                    postmaster.interceptors.replace(interceptors);
+                    postmaster.intercepting.store(!interceptors.is_empty(), Ordering::Relaxed);
+                }
+
+                /// Pushes a message onto the recipient's queue without waiting, applying the mailbox's overflow policy if the queue is full.
+                fn push_with_overflow(
+                    index: usize,
+                    registration: &Registration,
+                    mut message: Message,
+                ) -> Result<(), Undelivered> {
+                    let postmaster = postmaster();
+                    loop {
+                        message = match registration.mailbox.try_send(message) {
+                            Ok(()) => {
+                                record_queue_depth(index, &registration.mailbox);
+                                return Ok(());
+                            }
+                            #[cfg(not(target_os = "none"))]
+                            Err(TrySendError::Closed(message)) => return Err((PostmasterError::ReceiverClosed, message)),
+                            Err(TrySendError::Full(message)) => message,
+                        };
+                        let evictor = registration.mailbox.evictor();
+                        let evicted = match registration.overflow {
+                            OverflowPolicy::Block => return Err((PostmasterError::TrySendFailed, message)),
+                            OverflowPolicy::DropNewest => {
+                                postmaster.counters.dropped_messages[index].fetch_add(1, Ordering::Relaxed);
+                                return Ok(());
+                            }
+                            OverflowPolicy::DropOldest => evictor.and_then(|queue| queue.evict_oldest()),
+                            OverflowPolicy::OverwriteLatest => evictor.and_then(|queue| queue.evict_latest()),
+                        };
+                        // If the queue was emptied before a message could be evicted, there is now space for the new message anyway
+                        if evicted.is_some() {
+                            postmaster.counters.dropped_messages[index].fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+
+                /// Updates the high-water mark of the recipient's queue after a message has been added to it.
+                fn record_queue_depth(index: usize, mailbox: &Mailbox) {
+                    if let Some(depth) = mailbox.len() {
+                        postmaster().counters.queue_high_water_marks[index].fetch_max(depth, Ordering::Relaxed);
+                    }
+                }
+
+                /// Describes how a periodic message repeats.
+                pub(super) struct Repetition {
+                    pub(super) period: Duration,
+                    pub(super) copy_payload: fn(&$payload_enum) -> $payload_enum,
+                    /// The number of deliveries left to make, if limited.
+                    pub(super) remaining: Option<u32>,
+                }
+
+                impl Repetition {
+                    /// Produce a copy of the message for the next delivery, along with the period until the delivery after it.
+                    /// Returns `None` if this is the final delivery, in which case the original message should be delivered instead.
+                    fn next(&mut self, message: &Message) -> Option<(Message, Duration)> {
+                        if let Some(remaining) = self.remaining.as_mut() {
+                            if *remaining <= 1 {
+                                return None;
+                            }
+                            *remaining -= 1;
+                        }
+                        let copy = Message {
+                            priority: message.priority,
+                            envelope: message.envelope,
+                            ..Message::new(message.source, (self.copy_payload)(&message.payload))
+                        };
+                        Some((copy, self.period))
+                    }
+                }
+
+                pub(super) async fn spawn_delayed_send(
+                    destination: $address_enum,
+                    message: Message,
+                    delay: Duration,
+                    timeout: Option<Duration>,
+                    repetition: Option<Repetition>,
+                    retry: Option<Retry>,
+                ) -> Result<super::DelayedMessageHandle, PostmasterError> {
+                    start_scheduler()?;
+                    let (slot, generation) = with_schedule(|schedule| {
+                        schedule.insert(ScheduledMessage {
+                            destination,
+                            message,
+                            deadline: Instant::now() + delay,
+                            timeout,
+                            repetition,
+                            retry,
+                            blocked: None,
+                        })
+                    })?;
+                    wake_scheduler();
+                    Ok(super::DelayedMessageHandle { slot, generation })
+                }
+
+                pub(super) fn cancel_delayed_send(
+                    handle: &super::DelayedMessageHandle,
+                ) -> Result<(), PostmasterError> {
+                    with_schedule(|schedule| schedule.remove(handle.slot, handle.generation))
+                        .map(drop)
+                        .ok_or(PostmasterError::DelayedMessageNotPending)
+                }
+
+                pub(super) fn reschedule_delayed_send(
+                    handle: &super::DelayedMessageHandle,
+                    delay: Duration,
+                ) -> Result<(), PostmasterError> {
+                    let deadline = Instant::now() + delay;
+                    if with_schedule(|schedule| {
+                        schedule.reschedule(handle.slot, handle.generation, deadline)
+                    }) {
+                        wake_scheduler();
+                        Ok(())
+                    } else {
+                        Err(PostmasterError::DelayedMessageNotPending)
+                    }
+                }
+
+                /// The Postmaster's single scheduler task, which delivers every delayed and periodic message once it is due.
+                /// The scheduler never waits for space in a mailbox, so a message to a full mailbox does not hold up those due for other addresses (see `deliver_due()`).
+                /// The task only holds a weak reference to its Postmaster, so that it does not keep an `Instance` alive once every handle to it has been dropped.
+                /// It holds a strong reference only while it delivers messages, and stops once the Postmaster is gone.
+                #[cfg(not(target_os = "none"))]
+                async fn run_scheduler(postmaster: Weak<Postmaster>, wake: Arc<Notify>) {
+                    loop {
+                        let Some(current) = postmaster.upgrade() else {
+                            return;
+                        };
+                        match sync_scope(current, deliver_all_due) {
+                            Some(deadline) => {
+                                let _ = time::timeout_at(deadline, wake.notified()).await;
+                            }
+                            None => wake.notified().await,
+                        }
+                    }
+                }
+
+                /// The Postmaster's single scheduler task, which delivers every delayed and periodic message once it is due.
+                /// The scheduler never waits for space in a mailbox, so a message to a full mailbox does not hold up those due for other addresses (see `deliver_due()`).
+                #[cfg(target_os = "none")]
+                #[task]
+                async fn run_scheduler() {
+                    let postmaster = postmaster();
+                    loop {
+                        match deliver_all_due() {
+                            Some(deadline) => {
+                                let _ = postmaster.scheduler_wake.wait().with_deadline(deadline).await;
+                            }
+                            None => postmaster.scheduler_wake.wait().await,
+                        }
+                    }
+                }
+
+                /// Deliver every message which is due, returning the deadline of the next message in the schedule (if any).
+                fn deliver_all_due() -> Option<Instant> {
+                    while let Some(due) = with_schedule(|schedule| schedule.pop_due(Instant::now())) {
+                        deliver_due(due);
+                    }
+                    with_schedule(|schedule| schedule.next_deadline())
+                }
+
+                /// Spawn the scheduler task if it is not already running.
+                #[cfg(not(target_os = "none"))]
+                fn start_scheduler() -> Result<(), PostmasterError> {
+                    let postmaster = postmaster();
+                    let mut scheduler = postmaster.scheduler.lock().unwrap();
+                    // The scheduler is respawned if the runtime it was running on has shut down
+                    if scheduler.as_ref().is_none_or(|handle| handle.is_finished()) {
+                        let wake = Arc::clone(&postmaster.scheduler_wake);
+                        scheduler.replace(task::spawn(run_scheduler(Arc::downgrade(&postmaster), wake)));
+                    }
                     Ok(())
                 }
 
-                pub(super) fn try_send_internal(
+                /// Spawn the scheduler task if it is not already running.
+                #[cfg(target_os = "none")]
+                fn start_scheduler() -> Result<(), PostmasterError> {
+                    let postmaster = postmaster();
+                    if !postmaster.scheduler_started.load(Ordering::Relaxed) {
+                        let Some(spawner) = *postmaster.spawner.borrow() else {
+                            return Err(PostmasterError::SpawnerNotSet);
+                        };
+                        spawner.spawn(run_scheduler())?;
+                        postmaster.scheduler_started.store(true, Ordering::Relaxed);
+                    }
+                    Ok(())
+                }
+
+                fn wake_scheduler() {
+                    #[cfg(not(target_os = "none"))]
+                    postmaster().scheduler_wake.notify_one();
+                    #[cfg(target_os = "none")]
+                    postmaster().scheduler_wake.signal(());
+                }
+
+                fn with_schedule<R>(f: impl FnOnce(&mut Schedule) -> R) -> R {
+                    let postmaster = postmaster();
+                    #[cfg(not(target_os = "none"))]
+                    let mut schedule = postmaster.schedule.lock().unwrap();
+                    #[cfg(target_os = "none")]
+                    let mut schedule = postmaster.schedule.borrow_mut();
+                    f(&mut schedule)
+                }
+
+                /// Deliver a message which has fallen due, without waiting.
+                /// If the recipient's queue is full (or the Postmaster's table of mailboxes is locked), the message is put back in the schedule to be tried again every `BLOCKED_RETRY_INTERVAL`, until its timeout expires.
+                /// A message which is put back has already been stamped and passed through the interceptors, so this only happens once however many attempts are made.
+                fn deliver_due(mut due: ScheduledMessage) {
+                    let postmaster = postmaster();
+                    let sender = due.message.source;
+                    let source = sender.variant_index();
+                    let blocked = match due.blocked.take() {
+                        Some(blocked) => blocked,
+                        None => {
+                            let index = due.destination.variant_index();
+                            stamp(&mut due.message, Some(index));
+                            let index = match intercept(&postmaster, index, &mut due.message) {
+                                Ok(Some(index)) => index,
+                                Ok(None) => return,
+                                Err(error) => {
+                                    let result = settle(index, source, Err((error, due.message)), None);
+                                    return finish_due(sender, due.destination, result, due.timeout, due.retry);
+                                }
+                            };
+                            let timeout = due.timeout.unwrap_or_else(|| {
+                                Duration::from_micros(postmaster.timeout_us.load(Ordering::Relaxed).into())
+                            });
+                            Blocked {
+                                index,
+                                expires: Instant::now() + timeout,
+                                #[cfg(not(target_os = "none"))]
+                                record: capture(&postmaster, index, &due.message),
+                            }
+                        }
+                    };
+                    let index = blocked.index;
+                    let Ok(senders) = postmaster.senders.try_lock() else {
+                        return block_due(due, blocked);
+                    };
+                    let result = match &senders[index] {
+                        None => Err((PostmasterError::NoRecipient, due.message)),
+                        Some(registration) if registration.overflow != OverflowPolicy::Block => {
+                            push_with_overflow(index, registration, due.message)
+                        }
+                        Some(registration) => match registration.mailbox.try_send(due.message) {
+                            Ok(()) => {
+                                record_queue_depth(index, &registration.mailbox);
+                                Ok(())
+                            }
+                            Err(TrySendError::Full(message)) => {
+                                drop(senders);
+                                due.message = message;
+                                return block_due(due, blocked);
+                            }
+                            #[cfg(not(target_os = "none"))]
+                            Err(TrySendError::Closed(message)) => Err((PostmasterError::ReceiverClosed, message)),
+                        },
+                    };
+                    #[cfg(not(target_os = "none"))]
+                    commit(blocked.record, &result);
+                    // A message sent with a retry policy is only forwarded to the dead-letter address once the Postmaster has given up on it
+                    let result = settle(index, source, result, Some(&*senders).filter(|_| due.retry.is_none()));
+                    drop(senders);
+                    finish_due(sender, due.destination, result, due.timeout, due.retry);
+                }
+
+                /// Put a message which could not be pushed onto a full queue back in the schedule, or fail it with `Timeout` if its timeout has expired.
+                /// A periodic message's copies need a slot of their own while they wait, so on Embassy a copy fails with `DelayedMessagePoolFull` if there is none free.
+                fn block_due(mut due: ScheduledMessage, blocked: Blocked) {
+                    let now = Instant::now();
+                    let sender = due.message.source;
+                    if now >= blocked.expires {
+                        let postmaster = postmaster();
+                        let senders = postmaster.senders.try_lock().ok();
+                        let result = settle(
+                            blocked.index,
+                            sender.variant_index(),
+                            Err((PostmasterError::Timeout, due.message)),
+                            senders.as_deref().filter(|_| due.retry.is_none()),
+                        );
+                        drop(senders);
+                        return finish_due(sender, due.destination, result, due.timeout, due.retry);
+                    }
+                    due.deadline = (now + BLOCKED_RETRY_INTERVAL).min(blocked.expires);
+                    due.blocked = Some(blocked);
+                    let destination = due.destination;
+                    match with_schedule(|schedule| schedule.insert(due)) {
+                        Ok(_) => wake_scheduler(),
+                        Err(error) => report_delivery_failure(sender, destination, error),
+                    }
+                }
+
+                /// Handle the outcome of delivering a message which fell due: a failed delivery is retried if the message has a retry policy which allows it, and is otherwise reported to the delivery failure handler.
+                /// If the Postmaster gives up on a message with a retry policy, it is forwarded to the dead-letter address, unless the table of mailboxes is locked at the time.
+                fn finish_due(
+                    source: $address_enum,
+                    destination: $address_enum,
+                    result: Result<(), SendError>,
+                    timeout: Option<Duration>,
+                    retry: Option<Retry>,
+                ) {
+                    let Err(mut error) = result else {
+                        return;
+                    };
+                    if let (Some(retry), Some(message)) = (retry, error.message.take()) {
+                        let Some(message) = retry_or_give_up(destination, message, error.error, timeout, retry) else {
+                            return;
+                        };
+                        let postmaster = postmaster();
+                        if let Ok(senders) = postmaster.senders.try_lock() {
+                            forward_dead_letter(&senders, error.destination.variant_index(), error.error, message);
+                        }
+                    }
+                    report_delivery_failure(source, destination, error.error);
+                }
+
+                /// Attempt to deliver a message sent with a `RetryPolicy`.
+                /// If the attempt fails with an error which is retried, and the policy allows another attempt, the message is handed to the scheduler to be delivered again once the backoff has elapsed.
+                /// As the message may yet be delivered, it is only forwarded to the dead-letter address once the Postmaster has given up on it.
+                pub(super) async fn retry_delivery(
+                    destination: $address_enum,
+                    message: Message,
+                    timeout: Option<Duration>,
+                    retry: Retry,
+                ) -> Result<(), SendError> {
+                    let postmaster = postmaster();
+                    let Err(mut error) = deliver(destination.variant_index(), message, timeout, false).await else {
+                        return Ok(());
+                    };
+                    // Undelivered messages are always handed back, as dead-lettering was disabled
+                    let Some(message) = error.message.take() else {
+                        return Err(error);
+                    };
+                    let Some(message) = retry_or_give_up(destination, message, error.error, timeout, retry) else {
+                        return Ok(());
+                    };
+                    let senders = postmaster.senders.lock().await;
+                    error.message = forward_dead_letter(&senders, error.destination.variant_index(), error.error, message);
+                    Err(error)
+                }
+
+                /// Hand a message which failed with the given error to the scheduler for its next delivery attempt, if its retry policy allows one.
+                /// Returns the message if the Postmaster has given up on it, having counted it in `retries_exhausted`.
+                fn retry_or_give_up(
                     destination: $address_enum,
                     message: Message,
-                ) -> Result<(), PostmasterError> {
-                    evaluate_diagnostics(
-                        match &POSTMASTER.senders.try_lock()?[destination as usize] {
-                            None => Err(PostmasterError::NoRecipient),
-                            Some(sender) => {
-                                sender.try_send(message)?;
-                                Ok(())
+                    error: PostmasterError,
+                    timeout: Option<Duration>,
+                    mut retry: Retry,
+                ) -> Option<Message> {
+                    let counters = &postmaster().counters;
+                    let message = match retry.next(error) {
+                        Some(backoff) => match schedule_retry(destination, message, backoff, timeout, retry) {
+                            Ok(()) => {
+                                counters.retries.fetch_add(1, Ordering::Relaxed);
+                                return None;
                             }
+                            Err(message) => message,
                         },
-                    )
+                        None => message,
+                    };
+                    counters.retries_exhausted.fetch_add(1, Ordering::Relaxed);
+                    Some(message)
                 }
 
-                pub(super) async fn spawn_delayed_send(
+                /// Hand a message to the scheduler for its next delivery attempt, returning it if it cannot be scheduled.
+                fn schedule_retry(
                     destination: $address_enum,
                     message: Message,
-                    delay: Duration,
+                    backoff: Duration,
                     timeout: Option<Duration>,
-                ) -> Result<(), PostmasterError> {
+                    retry: Retry,
+                ) -> Result<(), Message> {
+                    if start_scheduler().is_err() {
+                        return Err(message);
+                    }
+                    with_schedule(|schedule| {
+                        if schedule.is_full() {
+                            return Err(message);
+                        }
+                        schedule
+                            .insert(ScheduledMessage {
+                                destination,
+                                message,
+                                deadline: Instant::now() + backoff,
+                                timeout,
+                                repetition: None,
+                                retry: Some(retry),
+                                blocked: None,
+                            })
+                            .expect("the schedule has space");
+                        Ok(())
+                    })?;
+                    wake_scheduler();
+                    Ok(())
+                }
+
+                /// A delayed or periodic message waiting in the schedule.
+                struct ScheduledMessage {
+                    destination: $address_enum,
+                    message: Message,
+                    deadline: Instant,
+                    timeout: Option<Duration>,
+                    repetition: Option<Repetition>,
+                    retry: Option<Retry>,
+                    /// Set once an attempt to deliver the message has found the recipient's queue full
+                    blocked: Option<Blocked>,
+                }
 
+                /// A delivery which is waiting for space in the recipient's queue.
+                struct Blocked {
+                    /// The index of the address to which the message is being delivered, as decided by the interceptors
+                    index: usize,
+                    /// The time at which the delivery fails with `Timeout`
+                    expires: Instant,
+                    /// The message as captured for the recording, if any, which is kept once the message has been delivered
                     #[cfg(not(target_os = "none"))]
-                    {
-                        let _ = tokio::spawn(delayed_send(destination, message, delay, timeout));
-                        Ok(())
+                    record: Option<Record<$address_enum>>,
+                }
+
+                /// How often the scheduler tries again to deliver a message to a full queue.
+                const BLOCKED_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+                /// A slot in the schedule, which holds a message while it is pending.
+                /// The generation is incremented each time the slot is emptied, so that a stale handle to a slot which has since been reused can be detected and rejected.
+                struct ScheduleSlot {
+                    generation: u32,
+                    /// The position of the slot's index in the schedule's heap
+                    position: usize,
+                    entry: Option<ScheduledMessage>,
+                }
+
+                #[cfg(not(target_os = "none"))]
+                type ScheduleStorage<T> = Vec<T>;
+                #[cfg(target_os = "none")]
+                type ScheduleStorage<T> = [T; DELAYED_MESSAGE_POOL_SIZE];
+
+                /// The queue of pending delayed messages, ordered by deadline.
+                /// This is an indexed binary min-heap: `heap` is a permutation of the slot indices, where the first `len` indices are the occupied slots arranged as a heap, and the remainder are the free slots.
+                /// Each slot records its own position in the heap, so that a pending message can be cancelled or rescheduled in O(log n) time.
+                /// On Embassy the storage is a fixed-size array of DELAYED_MESSAGE_POOL_SIZE slots, while on tokio it grows as needed.
+                struct Schedule {
+                    slots: ScheduleStorage<ScheduleSlot>,
+                    heap: ScheduleStorage<usize>,
+                    len: usize,
+                }
+
+                impl Schedule {
+                    #[cfg(not(target_os = "none"))]
+                    const fn new() -> Self {
+                        Self {
+                            slots: Vec::new(),
+                            heap: Vec::new(),
+                            len: 0,
+                        }
                     }
+
                     #[cfg(target_os = "none")]
-                    if let Some(spawner) = *POSTMASTER.spawner.borrow(){
-                            Ok(spawner.spawn(delayed_send(destination, message, delay, timeout))?)
-                        } else {
-                            Err(PostmasterError::SpawnerNotSet)
+                    const fn new() -> Self {
+                        let mut slots = [const {
+                            ScheduleSlot {
+                                generation: 0,
+                                position: 0,
+                                entry: None,
+                            }
+                        }; DELAYED_MESSAGE_POOL_SIZE];
+                        let mut heap = [0; DELAYED_MESSAGE_POOL_SIZE];
+                        let mut index = 0;
+                        while index < DELAYED_MESSAGE_POOL_SIZE {
+                            slots[index].position = index;
+                            heap[index] = index;
+                            index += 1;
+                        }
+                        Self {
+                            slots,
+                            heap,
+                            len: 0,
+                        }
+                    }
+
+                    /// Add a message to the schedule, returning the index and generation of the slot it occupies.
+                    fn insert(&mut self, entry: ScheduledMessage) -> Result<(usize, u32), PostmasterError> {
+                        if self.len == self.heap.len() {
+                            #[cfg(not(target_os = "none"))]
+                            {
+                                self.slots.push(ScheduleSlot {
+                                    generation: 0,
+                                    position: self.len,
+                                    entry: None,
+                                });
+                                self.heap.push(self.len);
+                            }
+                            #[cfg(target_os = "none")]
+                            return Err(PostmasterError::DelayedMessagePoolFull);
+                        }
+                        let index = self.heap[self.len];
+                        self.slots[index].entry = Some(entry);
+                        self.len += 1;
+                        self.sift_up(self.len - 1);
+                        Ok((index, self.slots[index].generation))
+                    }
+
+                    /// Remove a pending message from the schedule, provided its slot has not been emptied since the given generation.
+                    fn remove(&mut self, index: usize, generation: u32) -> Option<ScheduledMessage> {
+                        let slot = self.slots.get(index)?;
+                        if slot.entry.is_none() || slot.generation != generation {
+                            return None;
+                        }
+                        Some(self.remove_at(slot.position))
+                    }
+
+                    /// Change the deadline of a pending message, provided its slot has not been emptied since the given generation.
+                    fn reschedule(&mut self, index: usize, generation: u32, deadline: Instant) -> bool {
+                        let Some(slot) = self.slots.get_mut(index) else {
+                            return false;
+                        };
+                        match slot.entry.as_mut() {
+                            Some(entry) if slot.generation == generation => {
+                                entry.deadline = deadline;
+                                let position = slot.position;
+                                self.sift_up(position);
+                                self.sift_down(self.slots[index].position);
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+
+                    /// Returns true if there is no free slot in which to insert another message (Embassy only, as on tokio the schedule grows as needed).
+                    fn is_full(&self) -> bool {
+                        cfg!(target_os = "none") && self.len == self.heap.len()
+                    }
+
+                    fn next_deadline(&self) -> Option<Instant> {
+                        (self.len > 0).then(|| self.deadline(0))
+                    }
+
+                    /// Take the next message which is due for delivery, if any.
+                    /// Periodic messages remain in the schedule (with their deadline advanced by one period) until their final delivery, and a copy of the message is returned for each of the others.
+                    fn pop_due(&mut self, now: Instant) -> Option<ScheduledMessage> {
+                        if self.next_deadline()? > now {
+                            return None;
+                        }
+                        let entry = self.slots[self.heap[0]].entry.as_mut()?;
+                        if let Some((copy, period)) = entry
+                            .repetition
+                            .as_mut()
+                            .and_then(|repetition| repetition.next(&entry.message))
+                        {
+                            let delivery = ScheduledMessage {
+                                destination: entry.destination,
+                                message: copy,
+                                deadline: entry.deadline,
+                                timeout: entry.timeout,
+                                repetition: None,
+                                retry: None,
+                                blocked: None,
+                            };
+                            entry.deadline += period;
+                            self.sift_down(0);
+                            return Some(delivery);
+                        }
+                        Some(self.remove_at(0))
+                    }
+
+                    fn remove_at(&mut self, position: usize) -> ScheduledMessage {
+                        self.len -= 1;
+                        self.swap(position, self.len);
+                        if position < self.len {
+                            let moved = self.heap[position];
+                            self.sift_up(position);
+                            self.sift_down(self.slots[moved].position);
+                        }
+                        let slot = &mut self.slots[self.heap[self.len]];
+                        slot.generation = slot.generation.wrapping_add(1);
+                        slot.entry.take().unwrap()
+                    }
+
+                    fn deadline(&self, position: usize) -> Instant {
+                        self.slots[self.heap[position]]
+                            .entry
+                            .as_ref()
+                            .expect("every slot within the heap is occupied")
+                            .deadline
+                    }
+
+                    fn swap(&mut self, a: usize, b: usize) {
+                        self.heap.swap(a, b);
+                        self.slots[self.heap[a]].position = a;
+                        self.slots[self.heap[b]].position = b;
+                    }
+
+                    fn sift_up(&mut self, mut position: usize) {
+                        while position > 0 {
+                            let parent = (position - 1) / 2;
+                            if self.deadline(position) >= self.deadline(parent) {
+                                break;
+                            }
+                            self.swap(position, parent);
+                            position = parent;
                         }
+                    }
 
+                    fn sift_down(&mut self, mut position: usize) {
+                        loop {
+                            let mut earliest = position;
+                            for child in [2 * position + 1, 2 * position + 2] {
+                                if child < self.len && self.deadline(child) < self.deadline(earliest) {
+                                    earliest = child;
+                                }
+                            }
+                            if earliest == position {
+                                break;
+                            }
+                            self.swap(position, earliest);
+                            position = earliest;
+                        }
+                    }
                 }
 
-                #[cfg_attr(target_os = "none", task(pool_size=DELAYED_MESSAGE_POOL_SIZE))]
-                pub(super) async fn delayed_send(
-                    destination: $address_enum,
-                    message: Message,
-                    delay: Duration,
-                    timeout: Option<Duration>,
+                pub(super) fn set_delivery_failure_handler(
+                    handler: Option<fn(super::DeliveryFailure)>,
                 ) {
-                    sleep(delay).await;
-                    let source = message.source;
-                    match send_internal(destination, message, timeout).await {
-                        Ok(_) => (),
-                        Err(error) => (), // TODO: Can we find a way to convey back to the source that the sending failed?
+                    #[cfg(not(target_os = "none"))]
+                    {
+                        *postmaster().delivery_failure_handler.write().unwrap() = handler;
                     }
+                    #[cfg(target_os = "none")]
+                    postmaster().delivery_failure_handler.replace(handler);
                 }
 
-                #[cfg_attr(target_os = "none", task(pool_size=DELAYED_MESSAGE_POOL_SIZE))]
-                pub(super) async fn delayed_try_send(
+                fn report_delivery_failure(
+                    source: $address_enum,
                     destination: $address_enum,
-                    message: Message,
-                    delay: Duration,
+                    error: PostmasterError,
                 ) {
-                    sleep(delay).await;
-                    match try_send_internal(destination, message) {
-                        Ok(_) => (),
-                        Err(error) => (), // TODO: Can we find a way to convey back to the source that the sending failed?
+                    #[cfg(not(target_os = "none"))]
+                    let handler = *postmaster().delivery_failure_handler.read().unwrap();
+                    #[cfg(target_os = "none")]
+                    let handler = *postmaster().delivery_failure_handler.borrow();
+                    if let Some(handler) = handler {
+                        handler(super::DeliveryFailure {
+                            source,
+                            destination,
+                            error,
+                        });
                     }
                 }
 
+                #[cfg(not(target_os = "none"))]
+                pub(super) async fn request_internal(
+                    destination: $address_enum,
+                    source: $address_enum,
+                    payload: $payload_enum,
+                ) -> Result<Message, PostmasterError> {
+                    let (reply_sender, reply_receiver) = oneshot::channel();
+                    let mut message = Message::new(source, payload);
+                    // The request's envelope is filled in as it is sent
+                    message.reply_token = Some(ReplyToken {
+                        sender: reply_sender,
+                        request: message.envelope,
+                    });
+                    send_internal(destination, message, None).await?;
+                    time::timeout(request_timeout(), reply_receiver)
+                        .await
+                        .map_err(|_| PostmasterError::Timeout)?
+                        .map_err(|_| PostmasterError::NoReply)
+                }
+
+                #[cfg(target_os = "none")]
+                pub(super) async fn request_internal(
+                    destination: $address_enum,
+                    source: $address_enum,
+                    payload: $payload_enum,
+                ) -> Result<Message, PostmasterError> {
+                    let slot = SlotGuard::acquire(&postmaster().reply_slots)
+                        .ok_or(PostmasterError::ReplySlotPoolFull)?;
+                    let mut message = Message::new(source, payload);
+                    // The request's envelope is filled in as it is sent
+                    message.reply_token = Some(ReplyToken {
+                        slot: slot.index,
+                        generation: slot.generation(),
+                        request: message.envelope,
+                    });
+                    send_internal(destination, message, None).await?;
+                    Ok(slot.wait().with_timeout(request_timeout()).await?)
+                }
+
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn reply_internal(
+                    token: ReplyToken,
+                    mut message: Message,
+                ) -> Result<(), PostmasterError> {
+                    message.envelope = token.request.next();
+                    stamp(&mut message, None);
+                    evaluate_diagnostics(
+                        None,
+                        message.source.variant_index(),
+                        token
+                            .sender
+                            .send(message)
+                            .map_err(|_| PostmasterError::RequestExpired),
+                    )
+                }
+
+                #[cfg(target_os = "none")]
+                pub(super) fn reply_internal(
+                    token: ReplyToken,
+                    mut message: Message,
+                ) -> Result<(), PostmasterError> {
+                    message.envelope = token.request.next();
+                    stamp(&mut message, None);
+                    evaluate_diagnostics(
+                        None,
+                        message.source.variant_index(),
+                        if postmaster().reply_slots[token.slot].signal(token.generation, message) {
+                            Ok(())
+                        } else {
+                            Err(PostmasterError::RequestExpired)
+                        },
+                    )
+                }
+
                 pub(super) fn get_diagnostics() -> super::Diagnostics{
+                    let postmaster = postmaster();
+                    let counters = &postmaster.counters;
                     super::Diagnostics {
-                        messages_sent: POSTMASTER.messages_sent.load(Ordering::Relaxed),
-                        send_failures: POSTMASTER.send_failures.load(Ordering::Relaxed),
+                        messages_sent: counters.messages_sent.load(Ordering::Relaxed),
+                        send_failures: counters.send_failures.load(Ordering::Relaxed),
+                        #[cfg(not(target_os = "none"))]
+                        agent_panics: counters.agent_panics.load(Ordering::Relaxed),
+                        #[cfg(not(target_os = "none"))]
+                        agent_restarts: counters.agent_restarts.load(Ordering::Relaxed),
+                        dead_letters: counters.dead_letters.load(Ordering::Relaxed),
+                        retries: counters.retries.load(Ordering::Relaxed),
+                        retries_exhausted: counters.retries_exhausted.load(Ordering::Relaxed),
+                        messages_sent_to: load_all(&counters.messages_sent_to),
+                        messages_sent_from: load_all(&counters.messages_sent_from),
+                        send_failures_to: load_all(&counters.send_failures_to),
+                        send_failures_from: load_all(&counters.send_failures_from),
+                        send_failures_with: load_all(&counters.send_failures_with),
+                        dropped_messages: load_all(&counters.dropped_messages),
+                        queue_high_water_marks: load_all(&counters.queue_high_water_marks),
+                    }
+                }
+
+                fn load_all<const N: usize>(counters: &[AtomicUsize; N]) -> [usize; N] {
+                    core::array::from_fn(|index| counters[index].load(Ordering::Relaxed))
+                }
+
+                pub(super) fn set_latency_tracking(enabled: bool) {
+                    postmaster().latency_tracking.store(enabled, Ordering::Relaxed)
+                }
+
+                pub(super) fn get_latency_stats(address: $address_enum) -> LatencyStats {
+                    postmaster().latency[address.variant_index()].stats()
+                }
+
+                pub(super) fn reset_latency_stats() {
+                    for recorder in &postmaster().latency {
+                        recorder.reset();
+                    }
+                }
+
+                pub(super) fn message_received(message: &Message) {
+                    if let Some(destination) = message.destination.filter(|_| message.track_latency) {
+                        postmaster().latency[destination].record(message.envelope.sent_at.elapsed());
+                    }
+                }
+
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn report_agent_panic(restarted: bool) {
+                    let postmaster = postmaster();
+                    postmaster.counters.agent_panics.fetch_add(1, Ordering::Relaxed);
+                    if restarted {
+                        postmaster.counters.agent_restarts.fetch_add(1, Ordering::Relaxed);
                     }
                 }
 
                 pub(super) fn set_timeout(timeout_us: u32) {
-                    POSTMASTER.timeout_us.store(timeout_us, Ordering::Relaxed)
+                    postmaster().timeout_us.store(timeout_us, Ordering::Relaxed)
+                }
+
+                pub(super) fn set_request_timeout(timeout_us: u32) {
+                    postmaster()
+                        .request_timeout_us
+                        .store(timeout_us, Ordering::Relaxed)
+                }
+
+                fn request_timeout() -> Duration {
+                    Duration::from_micros(
+                        postmaster().request_timeout_us.load(Ordering::Relaxed).into(),
+                    )
                 }
 
                 #[cfg(target_os = "none")]
                 pub(super) fn set_spawner(spawner: Spawner) {
-                    if POSTMASTER.spawner.borrow().is_none() {
-                        POSTMASTER.spawner.replace(Some(spawner));
+                    if postmaster().spawner.borrow().is_none() {
+                        postmaster().spawner.replace(Some(spawner));
                     }
                 }
 
+                /// The state of a single Postmaster: the default instance behind the free functions in `postmaster`, or an independent `postmaster::Instance` (tokio only).
                 #[cfg(not(target_os = "none"))]
-                struct Postmaster {
-                    senders: Mutex<[Option<Mailbox>; ADDRESS_COUNT]>,
+                pub(super) struct Postmaster {
+                    senders: Mutex<Senders>,
+                    subscribers: [[AtomicBool; ADDRESS_COUNT]; TOPIC_COUNT],
                     timeout_us: AtomicU32,
-                    messages_sent: AtomicUsize,
-                    send_failures: AtomicUsize,
+                    request_timeout_us: AtomicU32,
+                    delivery_failure_handler: std::sync::RwLock<Option<fn(super::DeliveryFailure)>>,
+                    interceptors: std::sync::RwLock<&'static [Interceptor]>,
+                    intercepting: AtomicBool,
+                    schedule: std::sync::Mutex<Schedule>,
+                    scheduler_wake: Arc<Notify>,
+                    scheduler: std::sync::Mutex<Option<task::JoinHandle<()>>>,
+                    sequence: AtomicU32,
+                    counters: Counters,
+                    latency_tracking: AtomicBool,
+                    latency: [LatencyRecorder; ADDRESS_COUNT],
+                    recording: AtomicBool,
+                    recorder: std::sync::Mutex<Option<Recorder<$address_enum, $payload_enum>>>,
                 }
+
                 #[cfg(not(target_os = "none"))]
-                static POSTMASTER: Lazy<Postmaster> = Lazy::new(|| Postmaster {
-                    senders: Mutex::new([const { None }; ADDRESS_COUNT]),
-                    timeout_us: AtomicU32::new($timeout_us),
-                    messages_sent: AtomicUsize::new(0),
-                    send_failures: AtomicUsize::new(0),
-                });
+                impl Postmaster {
+                    pub(super) fn new() -> Self {
+                        Self {
+                            senders: Mutex::new([const { None }; ADDRESS_COUNT]),
+                            subscribers: [const { [const { AtomicBool::new(false) }; ADDRESS_COUNT] }; TOPIC_COUNT],
+                            timeout_us: AtomicU32::new($timeout_us),
+                            request_timeout_us: AtomicU32::new(DEFAULT_REQUEST_TIMEOUT_US),
+                            delivery_failure_handler: std::sync::RwLock::new(None),
+                            interceptors: std::sync::RwLock::new(&[]),
+                            intercepting: AtomicBool::new(false),
+                            schedule: std::sync::Mutex::new(Schedule::new()),
+                            scheduler_wake: Arc::new(Notify::const_new()),
+                            scheduler: std::sync::Mutex::new(None),
+                            sequence: AtomicU32::new(0),
+                            counters: Counters::new(),
+                            latency_tracking: AtomicBool::new(false),
+                            latency: [const { LatencyRecorder::new() }; ADDRESS_COUNT],
+                            recording: AtomicBool::new(false),
+                            recorder: std::sync::Mutex::new(None),
+                        }
+                    }
+                }
+
+                #[cfg(not(target_os = "none"))]
+                impl Drop for Postmaster {
+                    fn drop(&mut self) {
+                        // The scheduler may be waiting for a message which will now never be sent, so it is stopped rather than left to notice that the Postmaster has gone
+                        if let Ok(Some(scheduler)) = self.scheduler.get_mut().map(Option::take) {
+                            scheduler.abort();
+                        }
+                    }
+                }
+
+                #[cfg(not(target_os = "none"))]
+                static POSTMASTER: Lazy<Arc<Postmaster>> = Lazy::new(|| Arc::new(Postmaster::new()));
+
+                #[cfg(not(target_os = "none"))]
+                task_local! {
+                    /// The instance within whose scope the current task is running, if any.
+                    static CURRENT: Arc<Postmaster>;
+                }
+
+                /// The Postmaster which the current task is using: the instance within whose scope it is running, or otherwise the default instance.
+                #[cfg(not(target_os = "none"))]
+                fn postmaster() -> Arc<Postmaster> {
+                    CURRENT
+                        .try_with(Arc::clone)
+                        .unwrap_or_else(|_| Arc::clone(&POSTMASTER))
+                }
+
+                /// Runs a future within the scope of the given instance, so that the Postmaster it uses is that instance.
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn scope<F: Future>(
+                    postmaster: Arc<Postmaster>,
+                    future: F,
+                ) -> impl Future<Output = F::Output> {
+                    CURRENT.scope(postmaster, future)
+                }
+
+                /// Runs a function within the scope of the given instance, so that the Postmaster it uses is that instance.
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn sync_scope<R>(postmaster: Arc<Postmaster>, f: impl FnOnce() -> R) -> R {
+                    CURRENT.sync_scope(postmaster, f)
+                }
+
+                /// Spawns a task which uses the same Postmaster as the current task.
+                /// Tasks do not inherit the scope of the task which spawned them, so every task spawned on behalf of an instance must be spawned with this.
+                #[cfg(not(target_os = "none"))]
+                pub(super) fn spawn<F>(future: F) -> task::JoinHandle<F::Output>
+                where
+                    F: Future + Send + 'static,
+                    F::Output: Send + 'static,
+                {
+                    task::spawn(scope(postmaster(), future))
+                }
 
                 #[cfg(target_os = "none")]
-                unsafe impl Sync for Postmaster<'_> {}
+                unsafe impl Sync for Postmaster {}
                 #[cfg(target_os = "none")]
-                struct Postmaster<'a> {
-                    senders:
-                        Mutex<NoopRawMutex, [Option<DynamicSender<'a, Message>>; ADDRESS_COUNT]>,
+                struct Postmaster {
+                    senders: Mutex<NoopRawMutex, Senders>,
+                    subscribers: [[AtomicBool; ADDRESS_COUNT]; TOPIC_COUNT],
                     timeout_us: AtomicU32,
+                    request_timeout_us: AtomicU32,
                     spawner: RefCell<Option<Spawner>>,
-                    messages_sent: AtomicUsize,
-                    send_failures: AtomicUsize,
+                    delivery_failure_handler: RefCell<Option<fn(super::DeliveryFailure)>>,
+                    interceptors: RefCell<&'static [Interceptor]>,
+                    intercepting: AtomicBool,
+                    reply_slots: [Slot<Message>; REPLY_SLOT_POOL_SIZE],
+                    schedule: RefCell<Schedule>,
+                    scheduler_wake: Signal<NoopRawMutex, ()>,
+                    scheduler_started: AtomicBool,
+                    sequence: AtomicU32,
+                    counters: Counters,
+                    latency_tracking: AtomicBool,
+                    latency: [LatencyRecorder; ADDRESS_COUNT],
+                }
+
+                /// A slot in the Postmaster's fixed-size reply pool on Embassy, used to pass a value to a task which is waiting on it.
+                /// The generation is incremented each time the slot is released, so that a stale handle to a slot which has since been reused can be detected and rejected.
+                #[cfg(target_os = "none")]
+                struct Slot<T> {
+                    in_use: AtomicBool,
+                    generation: AtomicU32,
+                    signal: Signal<NoopRawMutex, T>,
+                }
+
+                #[cfg(target_os = "none")]
+                impl<T> Slot<T> {
+                    const fn new() -> Self {
+                        Self {
+                            in_use: AtomicBool::new(false),
+                            generation: AtomicU32::new(0),
+                            signal: Signal::new(),
+                        }
+                    }
+
+                    /// Pass a value to the task holding the slot, provided the slot has not been released since the given generation.
+                    fn signal(&self, generation: u32, value: T) -> bool {
+                        let current = self.in_use.load(Ordering::Relaxed)
+                            && self.generation.load(Ordering::Relaxed) == generation;
+                        if current {
+                            self.signal.signal(value);
+                        }
+                        current
+                    }
+                }
+
+                /// Reserves a slot from a pool, releasing it again when dropped (including if the task holding it is cancelled).
+                #[cfg(target_os = "none")]
+                struct SlotGuard<T: 'static> {
+                    pool: &'static [Slot<T>],
+                    index: usize,
+                }
+
+                #[cfg(target_os = "none")]
+                impl<T> SlotGuard<T> {
+                    fn acquire(pool: &'static [Slot<T>]) -> Option<Self> {
+                        let index = pool.iter().position(|slot| {
+                            slot.in_use
+                                .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                                .is_ok()
+                        })?;
+                        pool[index].signal.reset();
+                        Some(Self { pool, index })
+                    }
+
+                    fn generation(&self) -> u32 {
+                        self.pool[self.index].generation.load(Ordering::Relaxed)
+                    }
+
+                    async fn wait(&self) -> T {
+                        self.pool[self.index].signal.wait().await
+                    }
+                }
+
+                #[cfg(target_os = "none")]
+                impl<T> Drop for SlotGuard<T> {
+                    fn drop(&mut self) {
+                        let slot = &self.pool[self.index];
+                        slot.generation.fetch_add(1, Ordering::Relaxed);
+                        slot.signal.reset();
+                        slot.in_use.store(false, Ordering::Relaxed);
+                    }
                 }
 
                 #[cfg(target_os = "none")]
                 static POSTMASTER: Postmaster = Postmaster {
-                    senders: Mutex::new([None; ADDRESS_COUNT]),
+                    senders: Mutex::new([const { None }; ADDRESS_COUNT]),
+                    subscribers: [const { [const { AtomicBool::new(false) }; ADDRESS_COUNT] }; TOPIC_COUNT],
                     timeout_us: AtomicU32::new(100),
+                    request_timeout_us: AtomicU32::new(DEFAULT_REQUEST_TIMEOUT_US),
                     spawner: RefCell::new(None),
-                    messages_sent: AtomicUsize::new(0),
-                    send_failures: AtomicUsize::new(0),
+                    delivery_failure_handler: RefCell::new(None),
+                    interceptors: RefCell::new(&[]),
+                    intercepting: AtomicBool::new(false),
+                    reply_slots: [const { Slot::new() }; REPLY_SLOT_POOL_SIZE],
+                    schedule: RefCell::new(Schedule::new()),
+                    scheduler_wake: Signal::new(),
+                    scheduler_started: AtomicBool::new(false),
+                    sequence: AtomicU32::new(0),
+                    counters: Counters::new(),
+                    latency_tracking: AtomicBool::new(false),
+                    latency: [const { LatencyRecorder::new() }; ADDRESS_COUNT],
                 };
 
+                /// The Postmaster which the current task is using, which on Embassy is always the single static instance.
+                #[cfg(target_os = "none")]
+                fn postmaster() -> &'static Postmaster {
+                    &POSTMASTER
+                }
+
+                /// The counters from which the Postmaster's diagnostics are produced.
+                struct Counters {
+                    messages_sent: AtomicUsize,
+                    send_failures: AtomicUsize,
+                    #[cfg(not(target_os = "none"))]
+                    agent_panics: AtomicUsize,
+                    #[cfg(not(target_os = "none"))]
+                    agent_restarts: AtomicUsize,
+                    dead_letters: AtomicUsize,
+                    retries: AtomicUsize,
+                    retries_exhausted: AtomicUsize,
+                    messages_sent_to: [AtomicUsize; ADDRESS_COUNT],
+                    messages_sent_from: [AtomicUsize; ADDRESS_COUNT],
+                    send_failures_to: [AtomicUsize; ADDRESS_COUNT],
+                    send_failures_from: [AtomicUsize; ADDRESS_COUNT],
+                    send_failures_with: [AtomicUsize; ERROR_COUNT],
+                    dropped_messages: [AtomicUsize; ADDRESS_COUNT],
+                    queue_high_water_marks: [AtomicUsize; ADDRESS_COUNT],
+                }
+
+                impl Counters {
+                    const fn new() -> Self {
+                        Self {
+                            messages_sent: AtomicUsize::new(0),
+                            send_failures: AtomicUsize::new(0),
+                            #[cfg(not(target_os = "none"))]
+                            agent_panics: AtomicUsize::new(0),
+                            #[cfg(not(target_os = "none"))]
+                            agent_restarts: AtomicUsize::new(0),
+                            dead_letters: AtomicUsize::new(0),
+                            retries: AtomicUsize::new(0),
+                            retries_exhausted: AtomicUsize::new(0),
+                            messages_sent_to: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                            messages_sent_from: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                            send_failures_to: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                            send_failures_from: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                            send_failures_with: [const { AtomicUsize::new(0) }; ERROR_COUNT],
+                            dropped_messages: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                            queue_high_water_marks: [const { AtomicUsize::new(0) }; ADDRESS_COUNT],
+                        }
+                    }
+                }
+
+                /// Counts the outcome of sending a message from the source address to the destination address.
+                /// Replies to requests have no destination address, as they bypass the requester's mailbox.
                 #[inline]
                 fn evaluate_diagnostics(
+                    destination: Option<usize>,
+                    source: usize,
                     result: Result<(), PostmasterError>,
                 ) -> Result<(), PostmasterError> {
+                    record_outcome(destination, source, result.err());
                     result
-                        .inspect(|_| {
-                            POSTMASTER.messages_sent.fetch_add(1, Ordering::Relaxed);
-                        })
-                        .inspect_err(|_| {
-                            POSTMASTER.send_failures.fetch_add(1, Ordering::Relaxed);
-                        })
                 }
 
-                async fn sleep(delay: Duration){
-                    #[cfg(target_os = "none")]
-                    Timer::after(delay).await;
-                    #[cfg(not(target_os = "none"))]
-                    time::sleep(delay).await;
-
+                fn record_outcome(
+                    destination: Option<usize>,
+                    source: usize,
+                    error: Option<PostmasterError>,
+                ) {
+                    let postmaster = postmaster();
+                    let counters = &postmaster.counters;
+                    match error {
+                        None => {
+                            counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+                            counters.messages_sent_from[source].fetch_add(1, Ordering::Relaxed);
+                            if let Some(destination) = destination {
+                                counters.messages_sent_to[destination].fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        Some(error) => {
+                            counters.send_failures.fetch_add(1, Ordering::Relaxed);
+                            counters.send_failures_from[source].fetch_add(1, Ordering::Relaxed);
+                            counters.send_failures_with[error.variant_index()].fetch_add(1, Ordering::Relaxed);
+                            if let Some(destination) = destination {
+                                counters.send_failures_to[destination].fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
                 }
             }
         }
     };
+    ($address_enum:ty, $payload_enum:ty, topics = $topic_enum:ty, dead_letter = $dead_letter_address:expr) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, 1000, $topic_enum, Some($dead_letter_address));
+    };
+    ($address_enum:ty, $payload_enum:ty, dead_letter = $dead_letter_address:expr) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, 1000, post_haste::NoTopics, Some($dead_letter_address));
+    };
+    ($address_enum:ty, $payload_enum:ty, $timeout_us: expr, topics = $topic_enum:ty, dead_letter = $dead_letter_address:expr) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, $timeout_us, $topic_enum, Some($dead_letter_address));
+    };
+    ($address_enum:ty, $payload_enum:ty, $timeout_us: expr, dead_letter = $dead_letter_address:expr) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, $timeout_us, post_haste::NoTopics, Some($dead_letter_address));
+    };
+    ($address_enum:ty, $payload_enum:ty, $timeout_us: expr, topics = $topic_enum:ty) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, $timeout_us, $topic_enum, None);
+    };
+    ($address_enum:ty, $payload_enum:ty, topics = $topic_enum:ty) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, 1000, $topic_enum, None);
+    };
+    ($address_enum:ty, $payload_enum:ty, $timeout_us: expr) => {
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, $timeout_us, post_haste::NoTopics, None);
+    };
     ($address_enum:ty, $payload_enum:ty) => {
-        init_postmaster!($address_enum, $payload_enum, 1000);
+        $crate::init_postmaster!(@postmaster $address_enum, $payload_enum, 1000, post_haste::NoTopics, None);
     };
 }
+
+/// Implements serde's `Serialize` and `Deserialize` for the `Message` generated by `init_postmaster!()`, when the `serde` feature is enabled.
+/// The feature is checked here, rather than within `init_postmaster!()`, as a `cfg` in the generated code would check the features of the project's crate rather than this one.
+/// The bounds on the address and payload enums are quantified over a lifetime so that they are only checked where the traits are used, meaning projects whose enums do not implement the traits can still enable the feature.
+/// The reply token, the time at which the message was sent and any dead-letter details are not serialized, as they only have meaning within the Postmaster which sent the message.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _serde_message {
+    ($address_enum:ty, $payload_enum:ty) => {
+        impl post_haste::serialization::Serialize for Message
+        where
+            for<'a> $address_enum: post_haste::serialization::Serialize,
+            for<'a> $payload_enum: post_haste::serialization::Serialize,
+        {
+            fn serialize<S: post_haste::serialization::Serializer>(
+                &self,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                post_haste::serialization::Serialize::serialize(
+                    &post_haste::serialization::WireMessage {
+                        source: self.source,
+                        payload: &self.payload,
+                        priority: self.priority,
+                        sequence: self.envelope.sequence,
+                        correlation_id: self.envelope.correlation_id,
+                        hops: self.envelope.hops,
+                    },
+                    serializer,
+                )
+            }
+        }
+
+        /// A deserialized message has no reply token, and its `sent_at` time is the time at which it was deserialized.
+        impl<'de> post_haste::serialization::Deserialize<'de> for Message
+        where
+            for<'a> $address_enum: post_haste::serialization::Deserialize<'de>,
+            for<'a> $payload_enum: post_haste::serialization::Deserialize<'de>,
+        {
+            fn deserialize<D: post_haste::serialization::Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                let wire: post_haste::serialization::WireMessage<$address_enum, $payload_enum> =
+                    post_haste::serialization::Deserialize::deserialize(deserializer)?;
+                let mut message = Message::new(wire.source, wire.payload);
+                message.priority = wire.priority;
+                message.envelope.sequence = wire.sequence;
+                message.envelope.correlation_id = wire.correlation_id;
+                message.envelope.hops = wire.hops;
+                Ok(message)
+            }
+        }
+    };
+}
+
+/// Implements serde's `Serialize` and `Deserialize` for the generated `Message` when the `serde` feature is enabled, which it is not.
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _serde_message {
+    ($address_enum:ty, $payload_enum:ty) => {};
+}
+
+/// Implements `post_haste::bridge::BridgeMessage` for the `Message` generated by `init_postmaster!()`, when the `bridge` feature is enabled.
+/// As with `_serde_message!()`, the feature is checked here rather than within `init_postmaster!()`.
+#[cfg(feature = "bridge")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _bridge_message {
+    ($address_enum:ty) => {
+        impl post_haste::bridge::BridgeMessage<$address_enum> for Message {
+            fn destination(&self) -> Option<$address_enum> {
+                Message::destination(self)
+            }
+
+            async fn forward(
+                address: $address_enum,
+                via: $address_enum,
+            ) -> Result<(), PostmasterError> {
+                forward(address, via).await
+            }
+
+            async fn inject(
+                destination: $address_enum,
+                message: Message,
+            ) -> Result<(), PostmasterError> {
+                postmaster_internal::send_internal(destination, message, None)
+                    .await
+                    .map_err(|error| error.error)
+            }
+        }
+    };
+}
+
+/// Implements `post_haste::bridge::BridgeMessage` for the generated `Message` when the `bridge` feature is enabled, which it is not.
+#[cfg(not(feature = "bridge"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _bridge_message {
+    ($address_enum:ty) => {};
+}
@@ -0,0 +1,52 @@
+#[cfg(target_os = "none")]
+use embassy_sync::{blocking_mutex::raw::RawMutex, channel::Channel};
+
+/// Determines what happens when a message is sent to a mailbox whose queue is full.
+/// Chosen for each address when it is registered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The sender waits for space in the queue until its timeout expires (or fails immediately when using `try_send()`).
+    #[default]
+    Block,
+    /// The new message is discarded, and the send succeeds without waiting.
+    DropNewest,
+    /// The oldest message in the queue is discarded to make space for the new message, and the send succeeds without waiting.
+    /// Only supported by ordinary (FIFO) queues.
+    DropOldest,
+    /// The most recently queued message is replaced by the new message, and the send succeeds without waiting.
+    /// Only supported by ordinary (FIFO) queues.
+    /// Combined with a queue size of 1, this gives a "watch" mailbox which only ever holds the latest message sent to it.
+    OverwriteLatest,
+}
+
+impl OverflowPolicy {
+    /// Returns true if the policy requires the Postmaster to be able to remove messages from the queue.
+    pub fn evicts(&self) -> bool {
+        matches!(self, Self::DropOldest | Self::OverwriteLatest)
+    }
+}
+
+/// A message queue from which the Postmaster can remove messages, as required by the `DropOldest` and `OverwriteLatest` policies.
+pub trait Evict<T> {
+    /// Removes the oldest message in the queue (the next to be received), if any.
+    fn evict_oldest(&self) -> Option<T>;
+    /// Removes the most recently queued message, if any.
+    fn evict_latest(&self) -> Option<T>;
+}
+
+#[cfg(target_os = "none")]
+impl<M: RawMutex, T, const N: usize> Evict<T> for Channel<M, T, N> {
+    fn evict_oldest(&self) -> Option<T> {
+        self.try_receive().ok()
+    }
+
+    fn evict_latest(&self) -> Option<T> {
+        // Messages can only be removed from the front of the channel, so rotate the older messages to the back first
+        for _ in 1..self.len() {
+            if let Ok(message) = self.try_receive() {
+                let _ = self.try_send(message);
+            }
+        }
+        self.try_receive().ok()
+    }
+}
@@ -0,0 +1,72 @@
+/// The priority with which a message is delivered.
+/// Agents registered with a priority queue receive their messages highest priority first, so an urgent message does not have to wait behind lower priority messages already in the queue.
+/// Messages of equal priority are received in the order in which they were sent.
+/// Agents registered with an ordinary queue receive all messages in the order in which they were sent, regardless of priority.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Critical,
+}
+
+/// An item which can be queued in a priority queue, such as the `Message` generated by `init_postmaster!()`.
+/// Items are received highest priority first, and items of equal priority in order of their sequence numbers.
+pub trait Prioritised {
+    /// The priority with which the item is received.
+    fn priority(&self) -> Priority;
+
+    /// The sequence number of the item, which increases by one for each item sent (wrapping around after `u32::MAX`).
+    fn sequence(&self) -> u32;
+}
+
+/// An item held in a priority queue, along with the key by which the queue orders it.
+/// The ordering belongs to the queue rather than to the item, so the item itself need not implement `Ord`.
+#[doc(hidden)]
+pub struct Queued<T> {
+    priority: Priority,
+    sequence: u32,
+    item: T,
+}
+
+impl<T: Prioritised> Queued<T> {
+    pub fn new(item: T) -> Self {
+        Self {
+            priority: item.priority(),
+            sequence: item.sequence(),
+            item,
+        }
+    }
+}
+
+impl<T> Queued<T> {
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+/// Items are ordered by priority, and then by sequence number (earlier items being greater).
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.priority.cmp(&other.priority).then_with(|| {
+            // Compared as serial numbers, so that ordering is unaffected by the sequence number wrapping around
+            (other.sequence.wrapping_sub(self.sequence) as i32).cmp(&0)
+        })
+    }
+}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == core::cmp::Ordering::Equal
+    }
+}
+
+impl<T> Eq for Queued<T> {}
@@ -0,0 +1,359 @@
+//! Bounded message queues for tokio, with the same interface as `tokio::sync::mpsc`.
+//! Unlike `tokio::sync::mpsc`, these queues can deliver messages in priority order, and (for FIFO queues) allow the Postmaster to evict queued messages when applying an `OverflowPolicy`.
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError, TryRecvError, TrySendError};
+use tokio::sync::{Notify, Semaphore, TryAcquireError};
+use tokio::time::{self, Duration};
+
+use crate::overflow::Evict;
+use crate::priority::{Prioritised, Queued};
+
+enum Buffer<T> {
+    Fifo(VecDeque<T>),
+    /// Each item is wrapped by `queue` (i.e. `Queued::new`) as it is pushed, which records its priority
+    Priority {
+        heap: BinaryHeap<Queued<T>>,
+        queue: fn(T) -> Queued<T>,
+    },
+}
+
+impl<T> Buffer<T> {
+    fn push(&mut self, item: T) {
+        match self {
+            Self::Fifo(fifo) => fifo.push_back(item),
+            Self::Priority { heap, queue } => heap.push(queue(item)),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        match self {
+            Self::Fifo(fifo) => fifo.pop_front(),
+            Self::Priority { heap, .. } => heap.pop().map(Queued::into_inner),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Fifo(fifo) => fifo.len(),
+            Self::Priority { heap, .. } => heap.len(),
+        }
+    }
+}
+
+struct Shared<T> {
+    buffer: Mutex<Buffer<T>>,
+    /// One permit for each free space in the queue
+    capacity: Semaphore,
+    item_sent: Notify,
+    senders: AtomicUsize,
+}
+
+/// Creates a bounded queue which delivers items in the order in which they were sent, returning its sending and receiving halves.
+/// Panics if the capacity is 0.
+pub fn fifo_channel<T>(capacity: usize) -> (QueueSender<T>, QueueReceiver<T>) {
+    new_channel(Buffer::Fifo(VecDeque::with_capacity(capacity)), capacity)
+}
+
+/// Creates a bounded queue which delivers items highest priority first, and items of equal priority in order of their sequence numbers (see `Prioritised`), returning its sending and receiving halves.
+/// Panics if the capacity is 0.
+pub fn priority_channel<T: Prioritised>(capacity: usize) -> (QueueSender<T>, QueueReceiver<T>) {
+    new_channel(
+        Buffer::Priority {
+            heap: BinaryHeap::with_capacity(capacity),
+            queue: Queued::new,
+        },
+        capacity,
+    )
+}
+
+fn new_channel<T>(buffer: Buffer<T>, capacity: usize) -> (QueueSender<T>, QueueReceiver<T>) {
+    assert!(capacity > 0, "queue capacity cannot be zero");
+    let shared = Arc::new(Shared {
+        buffer: Mutex::new(buffer),
+        capacity: Semaphore::new(capacity),
+        item_sent: Notify::new(),
+        senders: AtomicUsize::new(1),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// The sending half of a queue created with `fifo_channel()` or `priority_channel()`.
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The receiving half of a queue created with `fifo_channel()` or `priority_channel()`.
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Sends an item, waiting until there is space in the queue.
+    /// Fails if the receiver has been dropped.
+    pub async fn send(&self, item: T) -> Result<(), SendError<T>> {
+        match self.shared.capacity.acquire().await {
+            Ok(permit) => {
+                permit.forget();
+                self.push(item);
+                Ok(())
+            }
+            Err(_) => Err(SendError(item)),
+        }
+    }
+
+    /// Sends an item, waiting until there is space in the queue or the timeout expires.
+    /// Fails if the timeout expires or the receiver has been dropped, returning the item.
+    pub async fn send_timeout(
+        &self,
+        item: T,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<T>> {
+        match time::timeout(timeout, self.shared.capacity.acquire()).await {
+            Ok(Ok(permit)) => {
+                permit.forget();
+                self.push(item);
+                Ok(())
+            }
+            Ok(Err(_)) => Err(SendTimeoutError::Closed(item)),
+            Err(_) => Err(SendTimeoutError::Timeout(item)),
+        }
+    }
+
+    /// Sends an item if there is space in the queue, without waiting.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        match self.shared.capacity.try_acquire() {
+            Ok(permit) => {
+                permit.forget();
+                self.push(item);
+                Ok(())
+            }
+            Err(TryAcquireError::NoPermits) => Err(TrySendError::Full(item)),
+            Err(TryAcquireError::Closed) => Err(TrySendError::Closed(item)),
+        }
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.shared.buffer.lock().unwrap().len()
+    }
+
+    /// Returns true if there are no items in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared.capacity.is_closed()
+    }
+
+    /// Returns true if the queue delivers items in the order in which they were sent.
+    pub fn is_fifo(&self) -> bool {
+        matches!(*self.shared.buffer.lock().unwrap(), Buffer::Fifo(_))
+    }
+
+    fn push(&self, item: T) {
+        self.shared.buffer.lock().unwrap().push(item);
+        self.shared.item_sent.notify_one();
+    }
+
+    fn evict(&self, pop: impl FnOnce(&mut VecDeque<T>) -> Option<T>) -> Option<T> {
+        let item = match &mut *self.shared.buffer.lock().unwrap() {
+            Buffer::Fifo(fifo) => pop(fifo),
+            Buffer::Priority { .. } => None,
+        };
+        if item.is_some() {
+            self.shared.capacity.add_permits(1);
+        }
+        item
+    }
+}
+
+/// Only FIFO queues support eviction: a priority queue never evicts any items.
+impl<T> Evict<T> for QueueSender<T> {
+    fn evict_oldest(&self) -> Option<T> {
+        self.evict(VecDeque::pop_front)
+    }
+
+    fn evict_latest(&self) -> Option<T> {
+        self.evict(VecDeque::pop_back)
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Wake the receiver so that it can see that the queue is closed
+            self.shared.item_sent.notify_one();
+        }
+    }
+}
+
+impl<T> QueueReceiver<T> {
+    /// Receives the next item in the queue, waiting until one is available.
+    /// Returns `None` once the queue is empty and either all senders have been dropped or the queue has been closed.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            match self.try_recv() {
+                Ok(item) => return Some(item),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => self.shared.item_sent.notified().await,
+            }
+        }
+    }
+
+    /// Receives the next item in the queue if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let senders = self.shared.senders.load(Ordering::Acquire);
+        match self.shared.buffer.lock().unwrap().pop() {
+            Some(item) => {
+                self.shared.capacity.add_permits(1);
+                Ok(item)
+            }
+            None if senders == 0 || self.shared.capacity.is_closed() => {
+                Err(TryRecvError::Disconnected)
+            }
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.shared.buffer.lock().unwrap().len()
+    }
+
+    /// Returns true if there are no items in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the queue, so that no further items can be sent.
+    /// Items already in the queue can still be received, after which the queue is disconnected.
+    pub fn close(&mut self) {
+        self.shared.capacity.close();
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.capacity.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::priority::Priority;
+
+    /// An item which is queued by its priority, and then by the order in which it was created
+    #[derive(Debug, PartialEq)]
+    struct Item(Priority, u32);
+
+    impl Prioritised for Item {
+        fn priority(&self) -> Priority {
+            self.0
+        }
+
+        fn sequence(&self) -> u32 {
+            self.1
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_returns_none_once_a_closed_queue_is_empty() {
+        let (sender, mut receiver) = fifo_channel(2);
+        sender.try_send(1).unwrap();
+        receiver.close();
+        assert!(matches!(sender.try_send(2), Err(TrySendError::Closed(2))));
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, None);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn recv_returns_none_once_all_senders_are_dropped() {
+        let (sender, mut receiver) = fifo_channel(2);
+        let clone = sender.clone();
+        sender.try_send(1).unwrap();
+        drop(sender);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        drop(clone);
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[test]
+    fn priority_queue_delivers_the_highest_priority_first() {
+        let (sender, mut receiver) = priority_channel(4);
+        for item in [
+            Item(Priority::Normal, 0),
+            Item(Priority::Low, 1),
+            Item(Priority::Critical, 2),
+            Item(Priority::Normal, 3),
+        ] {
+            sender.try_send(item).unwrap();
+        }
+        let received: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            [
+                Item(Priority::Critical, 2),
+                Item(Priority::Normal, 0),
+                Item(Priority::Normal, 3),
+                Item(Priority::Low, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn priority_queue_orders_sequence_numbers_across_wrapping() {
+        let (sender, mut receiver) = priority_channel(2);
+        sender.try_send(Item(Priority::Normal, 0)).unwrap();
+        sender.try_send(Item(Priority::Normal, u32::MAX)).unwrap();
+        assert_eq!(receiver.try_recv(), Ok(Item(Priority::Normal, u32::MAX)));
+        assert_eq!(receiver.try_recv(), Ok(Item(Priority::Normal, 0)));
+    }
+
+    #[test]
+    fn fifo_queue_evicts_the_oldest_and_latest_items() {
+        let (sender, mut receiver) = fifo_channel(3);
+        for item in [1, 2, 3] {
+            sender.try_send(item).unwrap();
+        }
+        assert!(matches!(sender.try_send(4), Err(TrySendError::Full(4))));
+        assert_eq!(sender.evict_oldest(), Some(1));
+        assert_eq!(sender.evict_latest(), Some(3));
+        // Each eviction frees a space in the queue
+        sender.try_send(4).unwrap();
+        sender.try_send(5).unwrap();
+        let received: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(received, [2, 4, 5]);
+        assert_eq!(sender.evict_oldest(), None);
+    }
+
+    #[test]
+    fn priority_queue_never_evicts() {
+        let (sender, receiver) = priority_channel(2);
+        sender.try_send(Item(Priority::Normal, 0)).unwrap();
+        assert_eq!(sender.evict_oldest(), None);
+        assert_eq!(sender.evict_latest(), None);
+        assert_eq!(receiver.len(), 1);
+    }
+}
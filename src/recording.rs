@@ -0,0 +1,377 @@
+//! Recording of the messages passing through the Postmaster, and replay of a recorded trace (tokio only).
+//! Payloads are recorded as bytes, using an encoding function given to `postmaster::start_recording()`, so that a trace can be saved on one machine and replayed on another.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use tokio::time::{Duration, Instant};
+
+use crate::variant_index::VariantIndex;
+
+/// The bytes with which every trace begins, identifying the format and its version.
+const MAGIC: [u8; 4] = *b"PHT1";
+
+/// Where the Postmaster keeps the messages it records.
+pub enum RecordingTarget {
+    /// Keep the given number of most recent messages in memory, discarding the oldest as new messages are recorded.
+    RingBuffer(usize),
+    /// Append every message to the given file as it is sent, in the format read by `Trace::load()`.
+    /// The file is written by a dedicated thread, so that sending a message never waits for the file.
+    /// The file is created (or truncated) when recording starts.
+    File(PathBuf),
+}
+
+/// A message recorded by the Postmaster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record<A> {
+    /// The time at which the message was sent, measured from the start of the recording
+    pub elapsed: Duration,
+    /// The address from which the message was sent
+    pub source: A,
+    /// The address to which the message was delivered (after any rerouting by interceptors)
+    pub destination: A,
+    /// The message's payload, as encoded by the function given to `postmaster::start_recording()`
+    pub payload: Vec<u8>,
+}
+
+/// A sequence of recorded messages, in the order in which they were delivered.
+/// The generated `postmaster` module provides an alias for this type, `postmaster::Trace`, with the project's address type filled in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace<A> {
+    pub records: Vec<Record<A>>,
+}
+
+impl<A> Default for Trace<A> {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+}
+
+impl<A: Copy + VariantIndex> Trace<A> {
+    /// Writes the trace to a file, which can be read back with `Trace::load()`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a trace from a file written by `Trace::save()`, or by recording to `RecordingTarget::File`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the trace in its binary format.
+    /// Addresses are written as their variant index, so a trace can only be read by a project with the same address enum.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        for record in &self.records {
+            write_record(&mut writer, record)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a trace in the format written by `Trace::write_to()`.
+    /// A trace whose final record was cut short (e.g. by the device losing power while recording to a file) is read up to the last complete record.
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a post-haste trace"));
+        }
+        let mut records = Vec::new();
+        while let Some(record) = read_record(&mut reader)? {
+            records.push(record);
+        }
+        Ok(Self { records })
+    }
+}
+
+fn write_record<A: VariantIndex>(writer: &mut impl Write, record: &Record<A>) -> io::Result<()> {
+    let elapsed_ns = u64::try_from(record.elapsed.as_nanos()).unwrap_or(u64::MAX);
+    writer.write_all(&elapsed_ns.to_le_bytes())?;
+    writer.write_all(&address_to_bytes(&record.source))?;
+    writer.write_all(&address_to_bytes(&record.destination))?;
+    let length = u32::try_from(record.payload.len())
+        .map_err(|_| invalid_data("payload is too large to record"))?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(&record.payload)
+}
+
+/// Reads the next record, returning `None` once the end of the trace has been reached.
+fn read_record<A: VariantIndex>(reader: &mut impl Read) -> io::Result<Option<Record<A>>> {
+    let mut header = [0; 20];
+    if !read_or_end(reader, &mut header)? {
+        return Ok(None);
+    }
+    let elapsed_ns = header[..8].try_into().expect("slice is 8 bytes");
+    let elapsed = Duration::from_nanos(u64::from_le_bytes(elapsed_ns));
+    let source = address_from_bytes(word(&header, 8))?;
+    let destination = address_from_bytes(word(&header, 12))?;
+    let mut payload = vec![0; u32::from_le_bytes(word(&header, 16)) as usize];
+    if !read_or_end(reader, &mut payload)? {
+        return Ok(None);
+    }
+    Ok(Some(Record {
+        elapsed,
+        source,
+        destination,
+        payload,
+    }))
+}
+
+fn word(header: &[u8; 20], offset: usize) -> [u8; 4] {
+    header[offset..offset + 4]
+        .try_into()
+        .expect("slice is 4 bytes")
+}
+
+/// Fills the buffer, returning false if the reader ran out of data first.
+fn read_or_end(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn address_to_bytes<A: VariantIndex>(address: &A) -> [u8; 4] {
+    (address.variant_index() as u32).to_le_bytes()
+}
+
+fn address_from_bytes<A: VariantIndex>(bytes: [u8; 4]) -> io::Result<A> {
+    A::from_variant_index(u32::from_le_bytes(bytes) as usize)
+        .ok_or_else(|| invalid_data("address is out of range"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Where a `Recorder` keeps its records.
+enum Sink<A> {
+    RingBuffer {
+        records: VecDeque<Record<A>>,
+        capacity: usize,
+    },
+    /// Records are passed to the thread writing the file, which returns the first error it encounters
+    File {
+        records: mpsc::Sender<Record<A>>,
+        writer: JoinHandle<io::Result<()>>,
+    },
+}
+
+/// Records the messages delivered by a Postmaster, whose addresses are of type `A` and payloads of type `P`.
+/// Used by the Postmaster generated by `init_postmaster!()`.
+#[doc(hidden)]
+pub struct Recorder<A, P> {
+    started: Instant,
+    encode: fn(&P) -> Vec<u8>,
+    sink: Sink<A>,
+}
+
+impl<A: Copy + VariantIndex + Send + 'static, P> Recorder<A, P> {
+    pub fn new(target: RecordingTarget, encode: fn(&P) -> Vec<u8>) -> io::Result<Self> {
+        let sink = match target {
+            RecordingTarget::RingBuffer(capacity) => Sink::RingBuffer {
+                records: VecDeque::with_capacity(capacity),
+                capacity,
+            },
+            RecordingTarget::File(path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                writer.write_all(&MAGIC)?;
+                let (records, received) = mpsc::channel();
+                let writer = thread::Builder::new()
+                    .name("post-haste-recorder".into())
+                    .spawn(move || write_records(writer, received))?;
+                Sink::File { records, writer }
+            }
+        };
+        Ok(Self {
+            started: Instant::now(),
+            encode,
+            sink,
+        })
+    }
+
+    /// Records a message as it is sent.
+    /// The record is only kept once it has been passed to `push()`, so that messages which could not be delivered are not recorded.
+    pub fn capture(&self, source: A, destination: A, payload: &P) -> Record<A> {
+        Record {
+            elapsed: self.started.elapsed(),
+            source,
+            destination,
+            payload: (self.encode)(payload),
+        }
+    }
+
+    pub fn push(&mut self, record: Record<A>) {
+        match &mut self.sink {
+            Sink::RingBuffer { records, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                records.push_back(record);
+            }
+            // If the writer has failed, the record is dropped, and the error is reported when recording stops
+            Sink::File { records, .. } => {
+                let _ = records.send(record);
+            }
+        }
+    }
+
+    /// The messages held in the ring buffer, or an empty trace if recording to a file.
+    pub fn trace(&self) -> Trace<A> {
+        match &self.sink {
+            Sink::RingBuffer { records, .. } => Trace {
+                records: records.iter().cloned().collect(),
+            },
+            Sink::File { .. } => Trace::default(),
+        }
+    }
+
+    /// Stops recording, returning the messages held in the ring buffer, or `None` once every record has been written to the file and flushed.
+    pub fn finish(self) -> io::Result<Option<Trace<A>>> {
+        match self.sink {
+            Sink::RingBuffer { records, .. } => Ok(Some(Trace {
+                records: records.into(),
+            })),
+            Sink::File { records, writer } => {
+                // Closing the channel lets the writer finish once it has written the records already sent
+                drop(records);
+                match writer.join() {
+                    Ok(result) => result.map(|_| None),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                }
+            }
+        }
+    }
+}
+
+/// Writes records to a file until the `Recorder` stops recording, flushing the file whenever it has caught up.
+fn write_records<A: VariantIndex>(
+    mut writer: BufWriter<File>,
+    records: mpsc::Receiver<Record<A>>,
+) -> io::Result<()> {
+    while let Ok(record) = records.recv() {
+        write_record(&mut writer, &record)?;
+        for record in records.try_iter() {
+            write_record(&mut writer, &record)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, crate::VariantIndex)]
+    enum Address {
+        Sensor,
+        Logger,
+    }
+
+    fn record(elapsed_ms: u64, payload: &[u8]) -> Record<Address> {
+        Record {
+            elapsed: Duration::from_millis(elapsed_ms),
+            source: Address::Sensor,
+            destination: Address::Logger,
+            payload: payload.to_vec(),
+        }
+    }
+
+    fn sample_trace() -> Trace<Address> {
+        Trace {
+            records: vec![record(0, b"first"), record(5, b""), record(12, b"third")],
+        }
+    }
+
+    fn encode(trace: &Trace<Address>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        trace.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn trace_round_trips_through_its_binary_format() {
+        let trace = sample_trace();
+        let bytes = encode(&trace);
+        assert_eq!(&bytes[..4], b"PHT1");
+        assert_eq!(Trace::read_from(bytes.as_slice()).unwrap(), trace);
+    }
+
+    #[test]
+    fn trace_with_the_wrong_magic_is_rejected() {
+        let mut bytes = encode(&sample_trace());
+        bytes[3] = b'2';
+        let error = Trace::<Address>::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_trace_is_read_up_to_its_last_complete_record() {
+        let trace = sample_trace();
+        let bytes = encode(&trace);
+        let mut complete = trace.clone();
+        complete.records.pop();
+        // Cut short within the final record's payload, and within its header
+        for cut in [1, 10] {
+            let truncated = &bytes[..bytes.len() - cut];
+            assert_eq!(Trace::read_from(truncated).unwrap(), complete);
+        }
+    }
+
+    #[test]
+    fn trace_with_an_unknown_address_is_rejected() {
+        let mut bytes = encode(&sample_trace());
+        // The source address of the first record follows the magic and its timestamp
+        bytes[12..16].copy_from_slice(&7u32.to_le_bytes());
+        let error = Trace::<Address>::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_most_recent_records() {
+        let mut recorder =
+            Recorder::<Address, u8>::new(RecordingTarget::RingBuffer(2), |byte| vec![*byte])
+                .unwrap();
+        for byte in 1..=3 {
+            let record = recorder.capture(Address::Sensor, Address::Logger, &byte);
+            recorder.push(record);
+        }
+        let payloads: Vec<_> = recorder
+            .trace()
+            .records
+            .into_iter()
+            .map(|record| record.payload)
+            .collect();
+        assert_eq!(payloads, [[2], [3]]);
+    }
+
+    #[test]
+    fn file_recording_can_be_loaded_once_finished() {
+        let path = std::env::temp_dir().join(format!("post-haste-{}.trace", std::process::id()));
+        let mut recorder =
+            Recorder::<Address, u8>::new(RecordingTarget::File(path.clone()), |byte| vec![*byte])
+                .unwrap();
+        let mut expected = Trace::default();
+        for byte in 1..=3 {
+            let record = recorder.capture(Address::Sensor, Address::Logger, &byte);
+            expected.records.push(record.clone());
+            recorder.push(record);
+        }
+        assert_eq!(recorder.finish().unwrap(), None);
+        let loaded = Trace::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), expected);
+    }
+}
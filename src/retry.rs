@@ -0,0 +1,73 @@
+use crate::PostmasterError;
+use crate::dependencies::Duration;
+
+/// Determines how the Postmaster re-attempts delivery of a message which could not be delivered.
+/// Applied to a message with `MessageBuilder::with_retry()`.
+/// The first retry is made once `initial_backoff` has elapsed, and the backoff before each retry after that is the previous backoff multiplied by `multiplier`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of delivery attempts, including the first.
+    /// A value of 0 is treated as 1, meaning the message is never retried.
+    pub max_attempts: u32,
+    /// The delay between the first attempt failing and the first retry
+    pub initial_backoff: Duration,
+    /// The factor by which the backoff grows after each retry.
+    /// A value of 1 retries at a fixed interval, while a value of 0 is treated as 1.
+    pub multiplier: u32,
+}
+
+impl RetryPolicy {
+    /// Creates a policy which makes at most `max_attempts` delivery attempts, waiting `initial_backoff` before the first retry and multiplying the backoff by `multiplier` after each retry.
+    /// As the function is `const`, a policy can be defined once as a constant and shared by every call site.
+    pub const fn new(max_attempts: u32, initial_backoff: Duration, multiplier: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff,
+            multiplier,
+        }
+    }
+
+    /// Returns true if a failure with the given error may resolve itself given time, so is worth retrying.
+    /// This is the case for `Timeout` and `TrySendFailed` (the recipient's queue being full), and for `NoRecipient` (the recipient not yet having been registered, e.g. during boot).
+    pub fn retries(error: PostmasterError) -> bool {
+        matches!(
+            error,
+            PostmasterError::Timeout
+                | PostmasterError::TrySendFailed
+                | PostmasterError::NoRecipient
+        )
+    }
+}
+
+/// Tracks the delivery attempts made for a single message sent with a `RetryPolicy`.
+/// Used by the Postmaster generated by `init_postmaster!()`.
+#[doc(hidden)]
+pub struct Retry {
+    policy: RetryPolicy,
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Retry {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            attempts: 0,
+            backoff: policy.initial_backoff,
+        }
+    }
+
+    /// Records a failed delivery attempt, and returns the backoff to wait before the next attempt.
+    /// Returns `None` if the message should not be retried, either because the error is not worth retrying or because every attempt has been used.
+    pub fn next(&mut self, error: PostmasterError) -> Option<Duration> {
+        self.attempts += 1;
+        if self.attempts >= self.policy.max_attempts.max(1) || !RetryPolicy::retries(error) {
+            return None;
+        }
+        let backoff = self.backoff;
+        self.backoff = backoff
+            .checked_mul(self.policy.multiplier.max(1))
+            .unwrap_or(backoff);
+        Some(backoff)
+    }
+}
@@ -0,0 +1,108 @@
+//! Serialization of messages, enabled by the `serde` feature.
+//! With the feature enabled, the generated `postmaster::Message` implements serde's `Serialize` and `Deserialize`, provided the project's address and payload enums do too.
+//! The helpers in this module encode values (such as messages or payloads) with postcard, a compact binary format which is suited to links between devices and to storage.
+#[doc(hidden)]
+pub use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub use postcard::Error;
+
+use crate::priority::Priority;
+
+/// The form in which the generated `postmaster::Message` is serialized.
+/// Only the parts of the message which are meaningful outside of the Postmaster which sent it are included.
+/// Used by the Postmaster generated by `init_postmaster!()`.
+#[doc(hidden)]
+#[derive(Serialize, Deserialize)]
+pub struct WireMessage<A, P> {
+    pub source: A,
+    pub payload: P,
+    pub priority: Priority,
+    pub sequence: u32,
+    pub correlation_id: u32,
+    pub hops: u16,
+}
+
+/// Encodes a value with postcard.
+#[cfg(not(target_os = "none"))]
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    postcard::to_extend(value, Vec::new())
+}
+
+/// Encodes a value with postcard into a buffer of up to `N` bytes.
+/// Fails with `SerializeBufferFull` if the encoded value does not fit.
+#[cfg(target_os = "none")]
+pub fn to_vec<T: Serialize + ?Sized, const N: usize>(
+    value: &T,
+) -> Result<heapless::Vec<u8, N>, Error> {
+    let mut buffer = heapless::Vec::new();
+    buffer
+        .resize_default(N)
+        .expect("buffer is resized to its capacity");
+    let length = postcard::to_slice(value, &mut buffer)?.len();
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+/// Decodes a value which was encoded with postcard, e.g. by `to_vec()`.
+pub fn from_bytes<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, Error> {
+    postcard::from_bytes(bytes)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    enum Address {
+        Controller,
+        Motor,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Payload {
+        SetSpeed(u16),
+        Log(Vec<u8>),
+    }
+
+    fn wire_message(payload: Payload) -> WireMessage<Address, Payload> {
+        WireMessage {
+            source: Address::Controller,
+            payload,
+            priority: Priority::High,
+            sequence: 42,
+            correlation_id: 7,
+            hops: 3,
+        }
+    }
+
+    #[test]
+    fn wire_message_round_trips() {
+        for payload in [Payload::SetSpeed(1200), Payload::Log(vec![0, 1, 0, 0, 2])] {
+            let bytes = to_vec(&wire_message(payload.clone())).unwrap();
+            let decoded: WireMessage<Address, Payload> = from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.source, Address::Controller);
+            assert_eq!(decoded.payload, payload);
+            assert_eq!(decoded.priority, Priority::High);
+            assert_eq!(
+                (decoded.sequence, decoded.correlation_id, decoded.hops),
+                (42, 7, 3)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_wire_message_is_not_decoded() {
+        let bytes = to_vec(&wire_message(Payload::Log(vec![1, 2, 3]))).unwrap();
+        for length in 0..bytes.len() {
+            assert!(from_bytes::<WireMessage<Address, Payload>>(&bytes[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn unknown_address_is_not_decoded() {
+        let mut bytes = to_vec(&wire_message(Payload::SetSpeed(1200))).unwrap();
+        // The source address is encoded first, as the index of its variant
+        bytes[0] = 2;
+        assert!(from_bytes::<WireMessage<Address, Payload>>(&bytes).is_err());
+    }
+}
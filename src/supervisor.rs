@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+
+use tokio::time::{Duration, Instant};
+
+/// Determines whether a supervised Agent is restarted after it panics.
+/// Passed to `register_agent!()` as its final argument, e.g. `restart = RestartStrategy::OneForOne`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// Only the Agent which panicked is restarted, however many times it panics.
+    OneForOne,
+    /// Only the Agent which panicked is restarted, unless it has already been restarted `max_restarts` times within the preceding `window`.
+    /// In that case the Agent is left stopped and its address is unregistered, so that sends to it fail with `NoRecipient`.
+    MaxRestartsInWindow { max_restarts: u32, window: Duration },
+}
+
+/// Tracks the restarts of a single supervised Agent, and decides whether it should be restarted again.
+#[doc(hidden)]
+pub struct Supervisor {
+    strategy: RestartStrategy,
+    restarts: VecDeque<Instant>,
+}
+
+impl Supervisor {
+    pub fn new(strategy: RestartStrategy) -> Self {
+        Self {
+            strategy,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Records that the supervised Agent has panicked, and returns whether it should be restarted.
+    pub fn should_restart(&mut self) -> bool {
+        match self.strategy {
+            RestartStrategy::OneForOne => true,
+            RestartStrategy::MaxRestartsInWindow {
+                max_restarts,
+                window,
+            } => {
+                let now = Instant::now();
+                while self
+                    .restarts
+                    .front()
+                    .is_some_and(|restart| now.duration_since(*restart) >= window)
+                {
+                    self.restarts.pop_front();
+                }
+                if self.restarts.len() < max_restarts as usize {
+                    self.restarts.push_back(now);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! A deterministic harness for testing Agents (tokio only).
+//! The `Harness` itself requires the `testing` feature, which enables tokio's `test-util` feature so that the harness can control the clock.
+#[cfg(feature = "testing")]
+use std::collections::VecDeque;
+#[cfg(feature = "testing")]
+use std::fmt::Debug;
+
+#[cfg(feature = "testing")]
+use tokio::sync::mpsc::{Receiver, channel};
+#[cfg(feature = "testing")]
+use tokio::task;
+#[cfg(feature = "testing")]
+use tokio::time::{self, Duration, Instant};
+
+use crate::PostmasterError;
+use crate::agent::InboxSender;
+#[cfg(feature = "testing")]
+use crate::variant_index::VariantIndex;
+
+/// Gives the test harness access to a Postmaster generated by `init_postmaster!()`, whose addresses are of type `A`.
+/// This trait is implemented for the generated `postmaster::Message` type, and should not need to be implemented by hand.
+/// The address type is a parameter of the trait (rather than an associated type) so that the project's address enum does not need to be public.
+#[allow(async_fn_in_trait)]
+pub trait TestMessage<A>: Sized {
+    /// The address from which the message originated
+    fn source(&self) -> A;
+
+    /// The sequence number stamped onto the message as it was sent
+    fn sequence(&self) -> u32;
+
+    /// Registers a mailbox to the given address, with the `Block` overflow policy.
+    async fn register(address: A, mailbox: InboxSender<Self>) -> Result<(), PostmasterError>;
+
+    /// Removes the mailbox registered to the given address.
+    async fn unregister(address: A) -> Result<(), PostmasterError>;
+}
+
+/// The number of messages each probe can hold before senders to it must wait.
+#[cfg(feature = "testing")]
+const PROBE_QUEUE_SIZE: usize = 64;
+
+/// The number of times the harness yields to the runtime when letting Agents process their messages.
+/// Each yield gives every task which is ready to run a turn, so this bounds the length of a chain of messages which can be passed between Agents before the harness checks its probes.
+#[cfg(feature = "testing")]
+const SETTLE_YIELDS: usize = 64;
+
+/// A harness for testing real Agents against fake "probe" mailboxes, using a virtual clock.
+///
+/// The harness requires tokio's clock to be paused from the start of the test, with `#[tokio::test(start_paused = true)]`, so that time only moves forward when `advance()` is called.
+/// This is a precondition rather than something the harness can check, and on a running clock `advance()` waits in real time.
+/// Delayed and periodic messages are therefore delivered exactly when the virtual clock reaches them, and tests do not need to sleep in real time.
+/// (Pausing the clock part way through a test with `tokio::time::pause()` is not enough, as timers would then expire up to 1 ms late.)
+///
+/// Probes are mailboxes registered by the harness in place of Agents.
+/// Every message received by a probe is recorded, in the order in which the messages were sent, and can be checked with `expect_message()`.
+///
+/// The harness uses whichever Postmaster the test is using, and unregisters every address when it is created.
+/// As the default Postmaster is shared by every test in the same binary, tests which use it must not run at the same time as each other (e.g. run them with `--test-threads=1`).
+/// To run tests in parallel, run the body of each test within the scope of its own `postmaster::Instance`, e.g. `postmaster::Instance::new().scope(async { ... }).await`.
+///
+/// # Examples
+/// ```ignore
+/// #[tokio::test(start_paused = true)]
+/// async fn pong_is_sent_after_delay() {
+///     let mut harness = Harness::<Address, postmaster::Message>::new().await;
+///     harness.probe(Address::Tester).await;
+///     postmaster::register_agent!(Ponger, PongAgent, ()).unwrap();
+///
+///     postmaster::send(Address::Ponger, Address::Tester, Payload::Ping).await.unwrap();
+///     harness.expect_no_messages().await;
+///     harness.advance(Duration::from_millis(100)).await;
+///     harness.expect_message(Address::Tester, Address::Ponger, |message| matches!(message.payload, Payload::Pong)).await;
+/// }
+/// ```
+#[cfg(feature = "testing")]
+pub struct Harness<A, M> {
+    probes: Vec<(A, Receiver<M>)>,
+    received: VecDeque<(A, M)>,
+    started: Instant,
+}
+
+#[cfg(feature = "testing")]
+impl<A: Copy + Debug + VariantIndex, M: TestMessage<A>> Harness<A, M> {
+    /// Creates the harness, unregistering any mailboxes left registered by a previous test.
+    /// Agents should be registered (with `postmaster::register_agent!()`) after the harness has been created.
+    /// The runtime's clock must already be paused (see `Harness`).
+    pub async fn new() -> Self {
+        for index in 0..A::VARIANT_COUNT {
+            let address = A::from_variant_index(index).expect("index is in range");
+            let _ = M::unregister(address).await;
+        }
+        Self {
+            probes: Vec::new(),
+            received: VecDeque::new(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Registers a probe mailbox at the given address, recording every message sent to it.
+    ///
+    /// # Panics
+    /// Panics if the address is already registered.
+    pub async fn probe(&mut self, address: A) {
+        let (sender, receiver) = channel(PROBE_QUEUE_SIZE);
+        if let Err(error) = M::register(address, sender.into()).await {
+            panic!("could not register a probe at {address:?}: {error:?}");
+        }
+        self.probes.push((address, receiver));
+    }
+
+    /// Moves the virtual clock forward by the given duration.
+    /// Any delayed messages (and timeouts) which fall due along the way are handled in order, as if the time had really passed.
+    pub async fn advance(&mut self, duration: Duration) {
+        // While every task is waiting, the paused clock jumps straight to the next timer, so a timer started part way through (e.g. by an Agent sending a delayed message) still expires at the right time
+        time::sleep_until(Instant::now() + duration).await;
+        self.settle().await;
+    }
+
+    /// The time on the virtual clock since the harness was created.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Checks that the next message received by any probe was sent to `to` from `from`, and that it satisfies `matcher` (e.g. by matching its payload).
+    /// The message is returned, so that it can be inspected further.
+    /// The virtual clock is not advanced, so a delayed message is only received once `advance()` has moved the clock past its delay.
+    ///
+    /// # Panics
+    /// Panics if no message has been received, or if the next message received does not match.
+    pub async fn expect_message(&mut self, to: A, from: A, matcher: impl FnOnce(&M) -> bool) -> M {
+        self.collect().await;
+        let Some((destination, message)) = self.received.pop_front() else {
+            panic!("expected a message to {to:?} from {from:?}, but no message was received");
+        };
+        let source = message.source();
+        assert!(
+            destination.variant_index() == to.variant_index()
+                && source.variant_index() == from.variant_index(),
+            "expected a message to {to:?} from {from:?}, but the next message received was to {destination:?} from {source:?}"
+        );
+        assert!(
+            matcher(&message),
+            "the message to {to:?} from {from:?} did not match"
+        );
+        message
+    }
+
+    /// Checks that no further messages have been received by any probe.
+    ///
+    /// # Panics
+    /// Panics if a message has been received which has not been checked with `expect_message()`.
+    pub async fn expect_no_messages(&mut self) {
+        self.collect().await;
+        if let Some((destination, message)) = self.received.front() {
+            panic!(
+                "expected no more messages, but a message was received by {destination:?} from {:?}",
+                message.source()
+            );
+        }
+    }
+
+    /// Lets every Agent process its messages, then records the messages received by the probes in the order in which they were sent.
+    async fn collect(&mut self) {
+        self.settle().await;
+        let start = self.received.len();
+        for (address, receiver) in &mut self.probes {
+            while let Ok(message) = receiver.try_recv() {
+                self.received.push_back((*address, message));
+            }
+        }
+        self.received.make_contiguous()[start..].sort_by_key(|(_, message)| message.sequence());
+    }
+
+    async fn settle(&self) {
+        for _ in 0..SETTLE_YIELDS {
+            task::yield_now().await;
+        }
+    }
+}
@@ -0,0 +1,903 @@
+//! Tests of the Postmaster, using a Postmaster generated within this crate (tokio only).
+use std::cell::RefCell;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{Receiver, channel};
+use tokio::time::{self, Instant, sleep};
+
+use crate::VariantIndex;
+use crate::error::PostmasterError;
+use crate::overflow::OverflowPolicy;
+use crate::queue::{QueueReceiver, fifo_channel};
+use crate::retry::RetryPolicy;
+#[cfg(feature = "testing")]
+use crate::testing::Harness;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Payloads {
+    Tick(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, VariantIndex, Serialize, Deserialize)]
+pub enum Addresses {
+    Sender,
+    Receiver,
+    Other,
+    DeadLetters,
+}
+
+// Only part of the generated API is exercised by the tests
+#[allow(dead_code, unused_imports)]
+mod generated {
+    use super::{Addresses, Payloads};
+    use crate::init_postmaster;
+
+    init_postmaster!(Addresses, Payloads, dead_letter = Addresses::DeadLetters);
+}
+use generated::postmaster;
+
+/// Registers a queue of the given size to an address of the current Postmaster instance.
+async fn register_queue(address: Addresses, queue_size: usize) -> Receiver<postmaster::Message> {
+    let (sender, receiver) = channel(queue_size);
+    postmaster::register(address, sender, OverflowPolicy::Block)
+        .await
+        .unwrap();
+    receiver
+}
+
+/// Registers a queue of the given size to the receiving address, created with `fifo_channel()` so that it supports every overflow policy.
+async fn register_fifo(
+    queue_size: usize,
+    overflow: OverflowPolicy,
+) -> QueueReceiver<postmaster::Message> {
+    let (sender, receiver) = fifo_channel(queue_size);
+    postmaster::register(Addresses::Receiver, sender, overflow)
+        .await
+        .unwrap();
+    receiver
+}
+
+/// Sends each of the ticks to the receiving address in turn, asserting that every send succeeds.
+async fn send_ticks(ticks: impl IntoIterator<Item = u32>) {
+    for tick in ticks {
+        let result =
+            postmaster::send(Addresses::Receiver, Addresses::Sender, Payloads::Tick(tick)).await;
+        assert!(result.is_ok());
+    }
+}
+
+/// Receives every message waiting in the queue, returning their ticks.
+fn queued_ticks(receiver: &mut QueueReceiver<postmaster::Message>) -> Vec<u32> {
+    std::iter::from_fn(|| receiver.try_recv().ok())
+        .map(|message| {
+            let Payloads::Tick(tick) = message.payload;
+            tick
+        })
+        .collect()
+}
+
+/// Registers a queue to the receiving address of the current Postmaster instance.
+async fn register_receiver() -> Receiver<postmaster::Message> {
+    register_queue(Addresses::Receiver, 8).await
+}
+
+/// Sends a message to the receiving address once the delay has elapsed.
+async fn send_delayed(tick: u32, delay_ms: u64) -> postmaster::DelayedMessageHandle {
+    send_delayed_to(Addresses::Receiver, tick, delay_ms).await
+}
+
+/// Sends a message to the given address once the delay has elapsed.
+async fn send_delayed_to(
+    destination: Addresses,
+    tick: u32,
+    delay_ms: u64,
+) -> postmaster::DelayedMessageHandle {
+    postmaster::message(destination, Addresses::Sender, Payloads::Tick(tick))
+        .with_delay(Duration::from_millis(delay_ms))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Creates a test harness with a probe at each of the given addresses.
+#[cfg(feature = "testing")]
+async fn harness(probes: &[Addresses]) -> Harness<Addresses, postmaster::Message> {
+    let mut harness = Harness::new().await;
+    for &address in probes {
+        harness.probe(address).await;
+    }
+    harness
+}
+
+/// Checks that the harness's next message is the given tick, sent to `to` from the sending address.
+#[cfg(feature = "testing")]
+async fn expect_tick(
+    harness: &mut Harness<Addresses, postmaster::Message>,
+    to: Addresses,
+    tick: u32,
+) {
+    harness
+        .expect_message(to, Addresses::Sender, |message| {
+            message.payload == Payloads::Tick(tick)
+        })
+        .await;
+}
+
+/// An interceptor which increments the tick of every message.
+#[cfg(feature = "testing")]
+fn increment(_: Addresses, message: &mut postmaster::Message) -> postmaster::Verdict {
+    let Payloads::Tick(tick) = &mut message.payload;
+    *tick += 1;
+    postmaster::Verdict::Pass
+}
+
+/// An interceptor which reroutes messages with an even tick to the other address.
+#[cfg(feature = "testing")]
+fn reroute_even(_: Addresses, message: &mut postmaster::Message) -> postmaster::Verdict {
+    match message.payload {
+        Payloads::Tick(tick) if tick % 2 == 0 => postmaster::Verdict::Reroute(Addresses::Other),
+        _ => postmaster::Verdict::Pass,
+    }
+}
+
+/// Sends a message to the given address, returning the error with which it failed to send.
+async fn send_undeliverable(destination: Addresses, timeout: Duration) -> postmaster::SendError {
+    let result = postmaster::message(destination, Addresses::Sender, Payloads::Tick(1))
+        .with_timeout(timeout)
+        .send()
+        .await;
+    let Err(error) = result else {
+        panic!("the message to {destination:?} was delivered");
+    };
+    error
+}
+
+/// Receives the next dead letter, checking that it is the undeliverable message and that it records why it could not be delivered.
+async fn expect_dead_letter(
+    dead_letters: &mut Receiver<postmaster::Message>,
+    destination: Addresses,
+    error: PostmasterError,
+) {
+    let message = dead_letters.recv().await.unwrap();
+    assert_eq!(message.source, Addresses::Sender);
+    assert_eq!(message.payload, Payloads::Tick(1));
+    let dead_letter = message.dead_letter.unwrap();
+    assert_eq!(dead_letter.destination, destination);
+    assert_eq!(dead_letter.error, error);
+}
+
+thread_local! {
+    /// The delivery failures reported to `record_failure()` on this thread's runtime.
+    static FAILURES: RefCell<Vec<(Addresses, PostmasterError)>> = const { RefCell::new(Vec::new()) };
+    /// The times at which `record_attempt()` has seen a message on this thread's runtime.
+    static ATTEMPTS: RefCell<Vec<Instant>> = const { RefCell::new(Vec::new()) };
+}
+
+/// An interceptor which records the time of each delivery attempt in `ATTEMPTS`.
+#[cfg(feature = "testing")]
+fn record_attempt(_: Addresses, _: &mut postmaster::Message) -> postmaster::Verdict {
+    ATTEMPTS.with_borrow_mut(|attempts| attempts.push(Instant::now()));
+    postmaster::Verdict::Pass
+}
+
+/// Sends a message to the receiving address under the given retry policy, which is handed to the scheduler if the first attempt fails.
+async fn send_with_retry(policy: RetryPolicy) -> Result<(), postmaster::SendError> {
+    postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1))
+        .with_timeout(Duration::from_millis(1))
+        .with_retry(policy)
+        .send()
+        .await
+}
+
+/// A delivery failure handler which records each failure's destination and error in `FAILURES`.
+fn record_failure(failure: postmaster::DeliveryFailure) {
+    FAILURES.with_borrow_mut(|failures| failures.push((failure.destination, failure.error)));
+}
+
+/// Receives the next message, returning its tick and the time at which it was received.
+async fn next_tick(receiver: &mut Receiver<postmaster::Message>) -> (u32, Instant) {
+    let message = receiver.recv().await.unwrap();
+    let Payloads::Tick(tick) = message.payload;
+    (tick, Instant::now())
+}
+
+#[tokio::test(start_paused = true)]
+async fn delayed_messages_are_delivered_in_order_of_deadline() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let start = Instant::now();
+            for (tick, delay_ms) in [(3, 30), (1, 10), (2, 20)] {
+                send_delayed(tick, delay_ms).await;
+            }
+            for tick in 1..=3 {
+                let (received, at) = next_tick(&mut receiver).await;
+                assert_eq!(received, tick);
+                assert!(at - start >= Duration::from_millis(10 * u64::from(tick)));
+            }
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelled_message_is_not_delivered() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let cancelled = send_delayed(1, 10).await;
+            let delivered = send_delayed(2, 20).await;
+            cancelled.cancel().unwrap();
+            assert_eq!(next_tick(&mut receiver).await.0, 2);
+            // The message has already been delivered, so it can no longer be cancelled
+            assert!(delivered.cancel().is_err());
+            sleep(Duration::from_millis(100)).await;
+            assert!(receiver.try_recv().is_err());
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn rescheduled_message_is_delivered_after_its_new_delay() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let start = Instant::now();
+            let rescheduled = send_delayed(1, 10).await;
+            send_delayed(2, 20).await;
+            rescheduled.reschedule(Duration::from_millis(30)).unwrap();
+            assert_eq!(next_tick(&mut receiver).await.0, 2);
+            let (tick, at) = next_tick(&mut receiver).await;
+            assert_eq!(tick, 1);
+            assert!(at - start >= Duration::from_millis(30));
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn periodic_message_stops_after_its_repeat_count() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let start = Instant::now();
+            postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(0))
+                .with_period(Duration::from_millis(10), Payloads::clone)
+                .with_repeat_count(3)
+                .send()
+                .await
+                .unwrap();
+            for delivery in 1..=3 {
+                let (_, at) = next_tick(&mut receiver).await;
+                assert!(at - start >= Duration::from_millis(10 * delivery));
+            }
+            sleep(Duration::from_millis(100)).await;
+            assert!(receiver.try_recv().is_err());
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelled_periodic_message_stops_repeating() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let handle =
+                postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(0))
+                    .with_period(Duration::from_millis(10), Payloads::clone)
+                    .send()
+                    .await
+                    .unwrap();
+            next_tick(&mut receiver).await;
+            handle.cancel().unwrap();
+            sleep(Duration::from_millis(100)).await;
+            assert!(receiver.try_recv().is_err());
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn blocked_destination_does_not_hold_up_other_deliveries() {
+    postmaster::Instance::new()
+        .scope(async {
+            postmaster::set_timeout(1_000_000);
+            let mut blocked = register_queue(Addresses::Receiver, 1).await;
+            let mut other = register_queue(Addresses::Other, 1).await;
+            let start = Instant::now();
+            send_delayed(1, 10).await;
+            send_delayed(2, 10).await;
+            send_delayed_to(Addresses::Other, 3, 20).await;
+            let (tick, at) = next_tick(&mut other).await;
+            assert_eq!(tick, 3);
+            assert_eq!(at - start, Duration::from_millis(20));
+            // The blocked message is delivered once there is space for it
+            assert_eq!(next_tick(&mut blocked).await.0, 1);
+            assert_eq!(next_tick(&mut blocked).await.0, 2);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn blocked_message_fails_once_its_timeout_expires() {
+    postmaster::Instance::new()
+        .scope(async {
+            postmaster::set_delivery_failure_handler(record_failure);
+            let mut receiver = register_queue(Addresses::Receiver, 1).await;
+            for tick in 1..=2 {
+                postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(tick))
+                    .with_delay(Duration::from_millis(10))
+                    .with_timeout(Duration::from_millis(5))
+                    .send()
+                    .await
+                    .unwrap();
+            }
+            sleep(Duration::from_millis(20)).await;
+            assert_eq!(
+                FAILURES.take(),
+                [(Addresses::Receiver, PostmasterError::Timeout)]
+            );
+            assert_eq!(next_tick(&mut receiver).await.0, 1);
+            assert!(receiver.try_recv().is_err());
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_receives_the_reply() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            postmaster::spawn(async move {
+                let request = receiver.recv().await.unwrap();
+                let Payloads::Tick(tick) = request.payload;
+                postmaster::reply(
+                    request.reply_token.unwrap(),
+                    Addresses::Receiver,
+                    Payloads::Tick(tick + 1),
+                )
+                .unwrap();
+            });
+            let reply =
+                postmaster::request(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1))
+                    .await
+                    .unwrap();
+            assert_eq!(reply.source, Addresses::Receiver);
+            assert_eq!(reply.payload, Payloads::Tick(2));
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_times_out_without_a_reply() {
+    postmaster::Instance::new()
+        .scope(async {
+            let _receiver = register_receiver().await;
+            let result =
+                postmaster::request(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1))
+                    .await;
+            assert!(matches!(result, Err(PostmasterError::Timeout)));
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_fails_when_the_reply_token_is_dropped() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            postmaster::spawn(async move {
+                drop(receiver.recv().await.unwrap());
+            });
+            let result =
+                postmaster::request(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1))
+                    .await;
+            assert!(matches!(result, Err(PostmasterError::NoReply)));
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn reply_fails_once_the_request_has_timed_out() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            let result =
+                postmaster::request(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1))
+                    .await;
+            assert!(result.is_err());
+            let request = receiver.recv().await.unwrap();
+            let result = postmaster::reply(
+                request.reply_token.unwrap(),
+                Addresses::Receiver,
+                Payloads::Tick(2),
+            );
+            assert_eq!(result, Err(PostmasterError::RequestExpired));
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn drop_newest_discards_messages_sent_to_a_full_queue() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_fifo(2, OverflowPolicy::DropNewest).await;
+            send_ticks(1..=3).await;
+            assert_eq!(queued_ticks(&mut receiver), [1, 2]);
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.dropped_messages(Addresses::Receiver), 1);
+            assert_eq!(diagnostics.messages_sent_to(Addresses::Receiver), 3);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn drop_oldest_makes_space_for_new_messages() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_fifo(2, OverflowPolicy::DropOldest).await;
+            send_ticks(1..=4).await;
+            assert_eq!(queued_ticks(&mut receiver), [3, 4]);
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.dropped_messages(Addresses::Receiver), 2);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn overwrite_latest_replaces_the_most_recent_message() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_fifo(2, OverflowPolicy::OverwriteLatest).await;
+            send_ticks(1..=4).await;
+            assert_eq!(queued_ticks(&mut receiver), [1, 4]);
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.dropped_messages(Addresses::Receiver), 2);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn watch_mailbox_holds_only_the_latest_message() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_fifo(1, OverflowPolicy::OverwriteLatest).await;
+            send_ticks(1..=3).await;
+            assert_eq!(queued_ticks(&mut receiver), [3]);
+            send_ticks([4]).await;
+            assert_eq!(queued_ticks(&mut receiver), [4]);
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.dropped_messages(Addresses::Receiver), 2);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn evicting_policy_requires_a_fifo_queue() {
+    postmaster::Instance::new()
+        .scope(async {
+            let (sender, _receiver) = channel(1);
+            let result =
+                postmaster::register(Addresses::Receiver, sender, OverflowPolicy::DropOldest).await;
+            assert_eq!(result, Err(PostmasterError::OverflowPolicyUnsupported));
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn interceptors_can_modify_messages_which_they_pass() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::Receiver]).await;
+            postmaster::set_interceptors(&[increment]);
+            send_ticks([1]).await;
+            send_delayed(3, 10).await;
+            expect_tick(&mut harness, Addresses::Receiver, 2).await;
+            harness.advance(Duration::from_millis(10)).await;
+            expect_tick(&mut harness, Addresses::Receiver, 4).await;
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn dropped_messages_are_discarded_and_counted() {
+    fn drop_all(_: Addresses, _: &mut postmaster::Message) -> postmaster::Verdict {
+        postmaster::Verdict::Drop
+    }
+
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::Receiver]).await;
+            postmaster::set_interceptors(&[drop_all]);
+            send_ticks([1]).await;
+            send_delayed(2, 10).await;
+            harness.advance(Duration::from_millis(10)).await;
+            harness.expect_no_messages().await;
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.dropped_messages(Addresses::Receiver), 2);
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn rejected_messages_fail_to_send() {
+    fn reject_all(_: Addresses, _: &mut postmaster::Message) -> postmaster::Verdict {
+        postmaster::Verdict::Reject
+    }
+
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::Receiver]).await;
+            postmaster::set_interceptors(&[reject_all]);
+            postmaster::set_delivery_failure_handler(record_failure);
+            let Err(error) =
+                postmaster::send(Addresses::Receiver, Addresses::Sender, Payloads::Tick(1)).await
+            else {
+                panic!("the message was not rejected");
+            };
+            assert_eq!(error.error, PostmasterError::Rejected);
+            assert!(error.message.is_some());
+            send_delayed(2, 10).await;
+            harness.advance(Duration::from_millis(10)).await;
+            harness.expect_no_messages().await;
+            assert_eq!(
+                FAILURES.take(),
+                [(Addresses::Receiver, PostmasterError::Rejected)]
+            );
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn rerouted_messages_are_delivered_to_the_new_address() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::Receiver, Addresses::Other]).await;
+            postmaster::set_interceptors(&[reroute_even]);
+            send_ticks([1, 2]).await;
+            send_delayed(4, 10).await;
+            expect_tick(&mut harness, Addresses::Receiver, 1).await;
+            expect_tick(&mut harness, Addresses::Other, 2).await;
+            harness.advance(Duration::from_millis(10)).await;
+            expect_tick(&mut harness, Addresses::Other, 4).await;
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn interceptors_are_called_in_order() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::Receiver, Addresses::Other]).await;
+            // Each interceptor sees the changes made by those before it
+            postmaster::set_interceptors(&[increment, reroute_even]);
+            send_ticks([1]).await;
+            expect_tick(&mut harness, Addresses::Other, 2).await;
+            postmaster::set_interceptors(&[reroute_even, increment]);
+            send_ticks([1]).await;
+            expect_tick(&mut harness, Addresses::Receiver, 2).await;
+            postmaster::clear_interceptors();
+            send_ticks([2]).await;
+            expect_tick(&mut harness, Addresses::Receiver, 2).await;
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn message_without_a_recipient_is_forwarded_to_the_dead_letter_address() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut dead_letters = register_queue(Addresses::DeadLetters, 8).await;
+            let error = send_undeliverable(Addresses::Receiver, Duration::from_millis(5)).await;
+            assert_eq!(error.error, PostmasterError::NoRecipient);
+            assert!(error.message.is_none());
+            expect_dead_letter(
+                &mut dead_letters,
+                Addresses::Receiver,
+                PostmasterError::NoRecipient,
+            )
+            .await;
+            assert_eq!(postmaster::get_diagnostics().dead_letters, 1);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn message_to_a_full_queue_is_forwarded_once_its_timeout_expires() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut dead_letters = register_queue(Addresses::DeadLetters, 8).await;
+            let _receiver = register_queue(Addresses::Receiver, 1).await;
+            send_ticks([0]).await;
+            let start = Instant::now();
+            let error = send_undeliverable(Addresses::Receiver, Duration::from_millis(5)).await;
+            assert_eq!(start.elapsed(), Duration::from_millis(5));
+            assert_eq!(error.error, PostmasterError::Timeout);
+            assert!(error.message.is_none());
+            expect_dead_letter(
+                &mut dead_letters,
+                Addresses::Receiver,
+                PostmasterError::Timeout,
+            )
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn message_to_a_closed_receiver_is_forwarded_to_the_dead_letter_address() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut dead_letters = register_queue(Addresses::DeadLetters, 8).await;
+            drop(register_receiver().await);
+            let error = send_undeliverable(Addresses::Receiver, Duration::from_millis(5)).await;
+            assert_eq!(error.error, PostmasterError::ReceiverClosed);
+            assert!(error.message.is_none());
+            expect_dead_letter(
+                &mut dead_letters,
+                Addresses::Receiver,
+                PostmasterError::ReceiverClosed,
+            )
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn delayed_message_which_cannot_be_delivered_is_forwarded_to_the_dead_letter_address() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut dead_letters = register_queue(Addresses::DeadLetters, 8).await;
+            send_delayed(1, 10).await;
+            expect_dead_letter(
+                &mut dead_letters,
+                Addresses::Receiver,
+                PostmasterError::NoRecipient,
+            )
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn undeliverable_message_is_handed_back_without_a_dead_letter_mailbox() {
+    postmaster::Instance::new()
+        .scope(async {
+            let error = send_undeliverable(Addresses::Receiver, Duration::from_millis(5)).await;
+            assert_eq!(error.error, PostmasterError::NoRecipient);
+            assert_eq!(error.message.unwrap().payload, Payloads::Tick(1));
+            assert_eq!(postmaster::get_diagnostics().dead_letters, 0);
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn retries_back_off_by_the_multiplier_until_delivered() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[]).await;
+            postmaster::set_interceptors(&[record_attempt]);
+            let start = Instant::now();
+            assert!(
+                send_with_retry(RetryPolicy::new(5, Duration::from_millis(10), 2))
+                    .await
+                    .is_ok()
+            );
+            // The recipient is registered between the third and fourth attempts
+            harness.advance(Duration::from_millis(50)).await;
+            harness.probe(Addresses::Receiver).await;
+            harness.advance(Duration::from_millis(19)).await;
+            harness.expect_no_messages().await;
+            harness.advance(Duration::from_millis(1)).await;
+            expect_tick(&mut harness, Addresses::Receiver, 1).await;
+            let attempts: Vec<_> = ATTEMPTS.take().into_iter().map(|at| at - start).collect();
+            assert_eq!(attempts, [0, 10, 30, 70].map(Duration::from_millis));
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.retries, 3);
+            assert_eq!(diagnostics.retries_exhausted, 0);
+        })
+        .await;
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn exhausted_retries_are_reported_and_forwarded_to_the_dead_letter_address() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = harness(&[Addresses::DeadLetters]).await;
+            postmaster::set_interceptors(&[record_attempt]);
+            postmaster::set_delivery_failure_handler(record_failure);
+            let start = Instant::now();
+            assert!(
+                send_with_retry(RetryPolicy::new(3, Duration::from_millis(10), 3))
+                    .await
+                    .is_ok()
+            );
+            harness.advance(Duration::from_millis(39)).await;
+            harness.expect_no_messages().await;
+            assert!(FAILURES.take().is_empty());
+            harness.advance(Duration::from_millis(1)).await;
+            let message = harness
+                .expect_message(Addresses::DeadLetters, Addresses::Sender, |message| {
+                    message.payload == Payloads::Tick(1)
+                })
+                .await;
+            let dead_letter = message.dead_letter.unwrap();
+            assert_eq!(dead_letter.destination, Addresses::Receiver);
+            assert_eq!(dead_letter.error, PostmasterError::NoRecipient);
+            assert_eq!(
+                FAILURES.take(),
+                [(Addresses::Receiver, PostmasterError::NoRecipient)]
+            );
+            let attempts: Vec<_> = ATTEMPTS.take().into_iter().map(|at| at - start).collect();
+            assert_eq!(attempts, [0, 10, 40].map(Duration::from_millis));
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.retries, 2);
+            assert_eq!(diagnostics.retries_exhausted, 1);
+            assert_eq!(diagnostics.dead_letters, 1);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn error_which_is_not_retried_fails_the_send() {
+    postmaster::Instance::new()
+        .scope(async {
+            drop(register_receiver().await);
+            let Err(error) =
+                send_with_retry(RetryPolicy::new(5, Duration::from_millis(10), 2)).await
+            else {
+                panic!("the message was delivered");
+            };
+            assert_eq!(error.error, PostmasterError::ReceiverClosed);
+            assert!(error.message.is_some());
+            let diagnostics = postmaster::get_diagnostics();
+            assert_eq!(diagnostics.retries, 0);
+            assert_eq!(diagnostics.retries_exhausted, 1);
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn instances_keep_their_mailboxes_separate() {
+    let first = postmaster::Instance::new();
+    let second = postmaster::Instance::new();
+    let mut first_receiver = first.scope(register_receiver()).await;
+    // The same address can be registered in each instance
+    let mut second_receiver = second.scope(register_receiver()).await;
+    first.scope(send_ticks([1])).await;
+    second.scope(send_ticks([2, 3])).await;
+    first.scope(send_delayed(4, 10)).await;
+    sleep(Duration::from_millis(10)).await;
+    assert_eq!(next_tick(&mut first_receiver).await.0, 1);
+    assert_eq!(next_tick(&mut first_receiver).await.0, 4);
+    assert!(first_receiver.try_recv().is_err());
+    assert_eq!(next_tick(&mut second_receiver).await.0, 2);
+    assert_eq!(next_tick(&mut second_receiver).await.0, 3);
+    assert!(second_receiver.try_recv().is_err());
+    let first_diagnostics = first.sync_scope(postmaster::get_diagnostics);
+    let second_diagnostics = second.sync_scope(postmaster::get_diagnostics);
+    assert_eq!(first_diagnostics.messages_sent_to(Addresses::Receiver), 2);
+    assert_eq!(second_diagnostics.messages_sent_to(Addresses::Receiver), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn instance_is_dropped_with_its_last_handle_despite_its_scheduler() {
+    let instance = postmaster::Instance::new();
+    let mut receiver = instance
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            send_delayed(1, 10).await;
+            send_delayed(2, 1000).await;
+            next_tick(&mut receiver).await;
+            receiver
+        })
+        .await;
+    drop(instance);
+    // Once the Postmaster has been dropped, so has its sender for the mailbox
+    let closed = time::timeout(Duration::from_millis(100), receiver.recv()).await;
+    assert!(matches!(closed, Ok(None)));
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test(start_paused = true)]
+async fn message_round_trips_through_postcard() {
+    use crate::priority::Priority;
+    use crate::serialization::{from_bytes, to_vec};
+
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            send_ticks([1]).await;
+            let result =
+                postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(2))
+                    .with_priority(Priority::High)
+                    .send()
+                    .await;
+            assert!(result.is_ok());
+            receiver.recv().await.unwrap();
+            let message = receiver.recv().await.unwrap();
+            let decoded: postmaster::Message = from_bytes(&to_vec(&message).unwrap()).unwrap();
+            assert_eq!(decoded.source, Addresses::Sender);
+            assert_eq!(decoded.payload, Payloads::Tick(2));
+            assert_eq!(decoded.priority, Priority::High);
+            assert_eq!(decoded.envelope.sequence, message.envelope.sequence);
+            assert_eq!(
+                decoded.envelope.correlation_id,
+                message.envelope.correlation_id
+            );
+            assert_eq!(decoded.envelope.hops, message.envelope.hops);
+            assert!(decoded.reply_token.is_none());
+        })
+        .await;
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test(start_paused = true)]
+async fn replay_fails_on_an_undecodable_payload() {
+    use crate::recording::{RecordingTarget, Trace};
+    use crate::serialization::{from_bytes, to_vec};
+
+    postmaster::Instance::new()
+        .scope(async {
+            let mut receiver = register_receiver().await;
+            postmaster::start_recording(RecordingTarget::RingBuffer(8), |payload| {
+                to_vec(payload).unwrap()
+            })
+            .unwrap();
+            send_ticks([1, 2]).await;
+            let mut trace: Trace<Addresses> = postmaster::stop_recording().unwrap().unwrap();
+            assert_eq!(next_tick(&mut receiver).await.0, 1);
+            assert_eq!(next_tick(&mut receiver).await.0, 2);
+            // The payload enum has a single variant, so no other variant index can be decoded
+            trace.records[1].payload[0] = 1;
+            let result = postmaster::replay(&trace, |bytes| from_bytes(bytes).ok()).await;
+            assert_eq!(result, Err(PostmasterError::UndecodablePayload));
+            // No message is sent, as every payload is decoded first
+            assert!(receiver.try_recv().is_err());
+        })
+        .await;
+}
+
+#[test]
+#[should_panic(expected = "period")]
+fn zero_period_is_rejected() {
+    let _ = postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(0))
+        .with_period(Duration::ZERO, Payloads::clone);
+}
+
+#[test]
+#[should_panic(expected = "repeat count")]
+fn zero_repeat_count_is_rejected() {
+    let _ = postmaster::message(Addresses::Receiver, Addresses::Sender, Payloads::Tick(0))
+        .with_period(Duration::from_millis(10), Payloads::clone)
+        .with_repeat_count(0);
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test(start_paused = true)]
+async fn harness_delivers_a_message_delayed_part_way_through_an_advance() {
+    postmaster::Instance::new()
+        .scope(async {
+            let mut harness = Harness::<Addresses, postmaster::Message>::new().await;
+            harness.probe(Addresses::Receiver).await;
+            postmaster::spawn(async {
+                sleep(Duration::from_millis(5)).await;
+                send_delayed(1, 10).await;
+            });
+            harness.advance(Duration::from_millis(14)).await;
+            harness.expect_no_messages().await;
+            harness.advance(Duration::from_millis(1)).await;
+            harness
+                .expect_message(Addresses::Receiver, Addresses::Sender, |message| {
+                    message.payload == Payloads::Tick(1)
+                })
+                .await;
+            assert_eq!(harness.elapsed(), Duration::from_millis(15));
+        })
+        .await;
+}
@@ -0,0 +1,15 @@
+/// Provides the number of variants in an enum, and a mapping between each variant and its index.
+/// The Postmaster uses this to size its tables of addresses and topics at compile time, and to look up the entry for a given address or topic.
+///
+/// This trait should not normally be implemented by hand: instead, derive it with `#[derive(VariantIndex)]`.
+/// The derive supports enums whose variants have no fields, and indexes the variants in the order they are declared.
+pub trait VariantIndex: Sized {
+    /// The number of variants in the enum
+    const VARIANT_COUNT: usize;
+
+    /// The index of this variant, in the range `0..VARIANT_COUNT`
+    fn variant_index(&self) -> usize;
+
+    /// The variant with the given index, or `None` if the index is out of range
+    fn from_variant_index(index: usize) -> Option<Self>;
+}